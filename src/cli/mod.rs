@@ -2,6 +2,7 @@ use std::process::ExitCode;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use lune::ColorChoice;
 
 pub(crate) mod build;
 pub(crate) mod list;
@@ -35,6 +36,9 @@ impl Default for CliSubcommand {
 pub struct Cli {
     #[clap(subcommand)]
     subcommand: Option<CliSubcommand>,
+    /// When to use colors in output - auto, always or never
+    #[clap(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
 }
 
 impl Cli {
@@ -43,6 +47,7 @@ impl Cli {
     }
 
     pub async fn run(self) -> Result<ExitCode> {
+        self.color.apply();
         match self.subcommand.unwrap_or_default() {
             CliSubcommand::Run(cmd) => cmd.run().await,
             CliSubcommand::List(cmd) => cmd.run().await,
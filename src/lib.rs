@@ -6,4 +6,4 @@ pub mod roblox;
 #[cfg(test)]
 mod tests;
 
-pub use crate::lune::{Runtime, RuntimeError};
+pub use crate::lune::{ColorChoice, Runtime, RuntimeError};
@@ -1,11 +1,11 @@
 use std::io::ErrorKind as IoErrorKind;
-use std::path::{PathBuf, MAIN_SEPARATOR};
+use std::path::MAIN_SEPARATOR;
 
 use bstr::{BString, ByteSlice};
 use mlua::prelude::*;
 use tokio::fs;
 
-use crate::lune::util::TableBuilder;
+use crate::lune::util::{paths::resolve_runtime_path, TableBuilder};
 
 mod copy;
 mod metadata;
@@ -32,12 +32,15 @@ pub fn create(lua: &Lua) -> LuaResult<LuaTable> {
 }
 
 async fn fs_read_file(lua: &Lua, path: String) -> LuaResult<LuaString> {
-    let bytes = fs::read(&path).await.into_lua_err()?;
+    let bytes = fs::read(resolve_runtime_path(lua, path))
+        .await
+        .into_lua_err()?;
 
     lua.create_string(bytes)
 }
 
-async fn fs_read_dir(_: &Lua, path: String) -> LuaResult<Vec<String>> {
+async fn fs_read_dir(lua: &Lua, path: String) -> LuaResult<Vec<String>> {
+    let path = resolve_runtime_path(lua, path);
     let mut dir_strings = Vec::new();
     let mut dir = fs::read_dir(&path).await.into_lua_err()?;
     while let Some(dir_entry) = dir.next_entry().await.into_lua_err()? {
@@ -50,7 +53,7 @@ async fn fs_read_dir(_: &Lua, path: String) -> LuaResult<Vec<String>> {
             )));
         }
     }
-    let mut dir_string_prefix = path;
+    let mut dir_string_prefix = path.to_string_lossy().to_string();
     if !dir_string_prefix.ends_with(MAIN_SEPARATOR) {
         dir_string_prefix.push(MAIN_SEPARATOR);
     }
@@ -66,55 +69,66 @@ async fn fs_read_dir(_: &Lua, path: String) -> LuaResult<Vec<String>> {
     Ok(dir_strings_no_prefix)
 }
 
-async fn fs_write_file(_: &Lua, (path, contents): (String, BString)) -> LuaResult<()> {
-    fs::write(&path, contents.as_bytes()).await.into_lua_err()
+async fn fs_write_file(lua: &Lua, (path, contents): (String, BString)) -> LuaResult<()> {
+    fs::write(resolve_runtime_path(lua, path), contents.as_bytes())
+        .await
+        .into_lua_err()
 }
 
-async fn fs_write_dir(_: &Lua, path: String) -> LuaResult<()> {
-    fs::create_dir_all(&path).await.into_lua_err()
+async fn fs_write_dir(lua: &Lua, path: String) -> LuaResult<()> {
+    fs::create_dir_all(resolve_runtime_path(lua, path))
+        .await
+        .into_lua_err()
 }
 
-async fn fs_remove_file(_: &Lua, path: String) -> LuaResult<()> {
-    fs::remove_file(&path).await.into_lua_err()
+async fn fs_remove_file(lua: &Lua, path: String) -> LuaResult<()> {
+    fs::remove_file(resolve_runtime_path(lua, path))
+        .await
+        .into_lua_err()
 }
 
-async fn fs_remove_dir(_: &Lua, path: String) -> LuaResult<()> {
-    fs::remove_dir_all(&path).await.into_lua_err()
+async fn fs_remove_dir(lua: &Lua, path: String) -> LuaResult<()> {
+    fs::remove_dir_all(resolve_runtime_path(lua, path))
+        .await
+        .into_lua_err()
 }
 
-async fn fs_metadata(_: &Lua, path: String) -> LuaResult<FsMetadata> {
-    match fs::metadata(path).await {
+async fn fs_metadata(lua: &Lua, path: String) -> LuaResult<FsMetadata> {
+    match fs::metadata(resolve_runtime_path(lua, path)).await {
         Err(e) if e.kind() == IoErrorKind::NotFound => Ok(FsMetadata::not_found()),
         Ok(meta) => Ok(FsMetadata::from(meta)),
         Err(e) => Err(e.into()),
     }
 }
 
-async fn fs_is_file(_: &Lua, path: String) -> LuaResult<bool> {
-    match fs::metadata(path).await {
+async fn fs_is_file(lua: &Lua, path: String) -> LuaResult<bool> {
+    match fs::metadata(resolve_runtime_path(lua, path)).await {
         Err(e) if e.kind() == IoErrorKind::NotFound => Ok(false),
         Ok(meta) => Ok(meta.is_file()),
         Err(e) => Err(e.into()),
     }
 }
 
-async fn fs_is_dir(_: &Lua, path: String) -> LuaResult<bool> {
-    match fs::metadata(path).await {
+async fn fs_is_dir(lua: &Lua, path: String) -> LuaResult<bool> {
+    match fs::metadata(resolve_runtime_path(lua, path)).await {
         Err(e) if e.kind() == IoErrorKind::NotFound => Ok(false),
         Ok(meta) => Ok(meta.is_dir()),
         Err(e) => Err(e.into()),
     }
 }
 
-async fn fs_move(_: &Lua, (from, to, options): (String, String, FsWriteOptions)) -> LuaResult<()> {
-    let path_from = PathBuf::from(from);
+async fn fs_move(
+    lua: &Lua,
+    (from, to, options): (String, String, FsWriteOptions),
+) -> LuaResult<()> {
+    let path_from = resolve_runtime_path(lua, from);
     if !path_from.exists() {
         return Err(LuaError::RuntimeError(format!(
             "No file or directory exists at the path '{}'",
             path_from.display()
         )));
     }
-    let path_to = PathBuf::from(to);
+    let path_to = resolve_runtime_path(lua, to);
     if !options.overwrite && path_to.exists() {
         return Err(LuaError::RuntimeError(format!(
             "A file or directory already exists at the path '{}'",
@@ -125,6 +139,11 @@ async fn fs_move(_: &Lua, (from, to, options): (String, String, FsWriteOptions))
     Ok(())
 }
 
-async fn fs_copy(_: &Lua, (from, to, options): (String, String, FsWriteOptions)) -> LuaResult<()> {
+async fn fs_copy(
+    lua: &Lua,
+    (from, to, options): (String, String, FsWriteOptions),
+) -> LuaResult<()> {
+    let from = resolve_runtime_path(lua, from);
+    let to = resolve_runtime_path(lua, to);
     copy(from, to, options).await
 }
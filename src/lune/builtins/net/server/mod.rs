@@ -10,7 +10,7 @@ use tokio::{net::TcpListener, pin};
 use mlua::prelude::*;
 use mlua_luau_scheduler::LuaSpawnExt;
 
-use crate::lune::util::TableBuilder;
+use crate::lune::util::{threads::ThreadTracker, TableBuilder};
 
 use super::config::ServeConfig;
 
@@ -47,7 +47,7 @@ pub async fn serve<'lua>(
     };
 
     let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
-    lua.spawn_local(async move {
+    ThreadTracker::spawn_source(lua, async move {
         let mut shutdown_rx_outer = shutdown_rx.clone();
         loop {
             // Create futures for accepting new connections and shutting down
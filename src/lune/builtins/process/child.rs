@@ -0,0 +1,198 @@
+use std::process::ExitStatus;
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSpawnExt;
+use tokio::{
+    io,
+    process::Child,
+    sync::{mpsc, oneshot, watch},
+};
+
+use crate::lune::util::TableBuilder;
+
+use super::{
+    pty::{Pty, PtySize},
+    signal::{exit_code_and_signal, ProcessSignal},
+    stream::{ChildProcessReader, ChildProcessWriter},
+};
+
+type KillRequest = (Option<ProcessSignal>, oneshot::Sender<io::Result<()>>);
+type StatusResult = LuaResult<ExitStatus>;
+
+/**
+    A handle to a running child process, created using `process.create`.
+
+    The child process is owned by a background task that waits for it to exit,
+    any requests to kill the child process are forwarded to that same task.
+*/
+pub struct ChildProcess {
+    pid: Option<u32>,
+    pty: Option<Pty>,
+    stdin: ChildProcessWriter,
+    stdout: ChildProcessReader,
+    stderr: ChildProcessReader,
+    kill_tx: mpsc::UnboundedSender<KillRequest>,
+    status_rx: watch::Receiver<Option<StatusResult>>,
+}
+
+impl ChildProcess {
+    pub fn new(lua: &Lua, mut child: Child) -> Self {
+        let stdin = ChildProcessWriter::new(child.stdin.take().expect("stdin must be piped"));
+        let stdout = ChildProcessReader::new(child.stdout.take().expect("stdout must be piped"));
+        let stderr = ChildProcessReader::new(child.stderr.take().expect("stderr must be piped"));
+        Self::from_parts(lua, child, None, stdin, stdout, stderr)
+    }
+
+    /**
+        Creates a handle for a child process that is connected to a pseudo-terminal.
+
+        All output from the child process is given through stdout, the same
+        way a terminal would display it, and stderr is always empty.
+    */
+    pub fn new_pty(lua: &Lua, child: Child, pty: Pty) -> io::Result<Self> {
+        let stdin = ChildProcessWriter::new(pty.writer()?);
+        let stdout = ChildProcessReader::new(pty.reader()?);
+        let stderr = ChildProcessReader::new(io::empty());
+        Ok(Self::from_parts(
+            lua,
+            child,
+            Some(pty),
+            stdin,
+            stdout,
+            stderr,
+        ))
+    }
+
+    fn from_parts(
+        lua: &Lua,
+        mut child: Child,
+        pty: Option<Pty>,
+        stdin: ChildProcessWriter,
+        stdout: ChildProcessReader,
+        stderr: ChildProcessReader,
+    ) -> Self {
+        let pid = child.id();
+
+        let (kill_tx, mut kill_rx) = mpsc::unbounded_channel::<KillRequest>();
+        let (status_tx, status_rx) = watch::channel(None);
+
+        lua.spawn(async move {
+            let status = loop {
+                tokio::select! {
+                    res = child.wait() => break res,
+                    Some((signal, reply)) = kill_rx.recv() => {
+                        reply.send(kill_child(&mut child, signal)).ok();
+                    }
+                }
+            };
+            // NOTE: Waiting may fail if the status of the child could not be
+            // read, which is given to anyone calling status() as an error
+            let status = status
+                .into_lua_err()
+                .context("Failed to get status of child process");
+            status_tx.send(Some(status)).ok();
+        })
+        .detach();
+
+        Self {
+            pid,
+            pty,
+            stdin,
+            stdout,
+            stderr,
+            kill_tx,
+            status_rx,
+        }
+    }
+
+    pub async fn kill(&self, signal: Option<ProcessSignal>) -> LuaResult<()> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if self.kill_tx.send((signal, reply_tx)).is_err() {
+            // The child has already exited, there is nothing left to kill
+            return Ok(());
+        }
+        match reply_rx.await {
+            Ok(res) => res.into_lua_err(),
+            Err(_) => Ok(()),
+        }
+    }
+
+    pub fn resize(&self, size: PtySize) -> LuaResult<()> {
+        match &self.pty {
+            Some(pty) => pty.resize(size).into_lua_err(),
+            None => Err(LuaError::runtime(
+                "Child process is not connected to a pseudo-terminal",
+            )),
+        }
+    }
+
+    pub async fn status(&self) -> LuaResult<ExitStatus> {
+        let mut rx = self.status_rx.clone();
+        let status = rx
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| LuaError::runtime("Child process status is no longer available"))?;
+        match status.as_ref().expect("status was checked to be Some") {
+            Ok(status) => Ok(*status),
+            Err(e) => Err(e.clone()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill_child(child: &mut Child, signal: Option<ProcessSignal>) -> io::Result<()> {
+    match (signal, child.id()) {
+        (Some(signal), Some(pid)) => {
+            #[allow(clippy::cast_possible_wrap)]
+            let res = unsafe { libc::kill(pid as libc::pid_t, signal.number()) };
+            if res == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+        _ => child.start_kill(),
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_child(child: &mut Child, _: Option<ProcessSignal>) -> io::Result<()> {
+    child.start_kill()
+}
+
+impl LuaUserData for ChildProcess {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "ChildProcess");
+        fields.add_field_method_get("pid", |_, this| Ok(this.pid));
+        fields.add_field_method_get("stdin", |_, this| Ok(this.stdin.clone()));
+        fields.add_field_method_get("stdout", |_, this| Ok(this.stdout.clone()));
+        fields.add_field_method_get("stderr", |_, this| Ok(this.stderr.clone()));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method(
+            "kill",
+            |_, this, signal: Option<ProcessSignal>| async move { this.kill(signal).await },
+        );
+
+        methods.add_method("resize", |_, this, (rows, cols): (u16, u16)| {
+            this.resize(PtySize { rows, cols })
+        });
+
+        methods.add_async_method("status", |lua, this, _: ()| async move {
+            let status = this.status().await?;
+            let (code, signal) = exit_code_and_signal(status);
+            TableBuilder::new(lua)?
+                .with_value("ok", code == 0)?
+                .with_value("code", code)?
+                .with_value(
+                    "signal",
+                    signal
+                        .and_then(ProcessSignal::from_number)
+                        .map(|s| s.to_string()),
+                )?
+                .with_value("signalNumber", signal)?
+                .build_readonly()
+        });
+    }
+}
@@ -0,0 +1,129 @@
+use std::env;
+
+use directories::UserDirs;
+use mlua::prelude::*;
+
+use crate::lune::util::{process::START_TIME, TableBuilder};
+
+/*
+    NOTE: Some of the information here is only available on unix platforms,
+    and we return nil for it on other platforms instead of erroring, so that
+    diagnostics scripts can still run everywhere without special handling
+*/
+
+#[cfg(unix)]
+pub(super) fn parent_pid() -> Option<u32> {
+    #[allow(clippy::cast_sign_loss)]
+    Some(unsafe { libc::getppid() } as u32)
+}
+
+#[cfg(not(unix))]
+pub(super) fn parent_pid() -> Option<u32> {
+    None
+}
+
+pub(super) fn executable() -> Option<String> {
+    env::current_exe()
+        .ok()
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+#[cfg(unix)]
+pub(super) fn hostname() -> Option<String> {
+    let mut buf = vec![0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if res != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    buf.truncate(len);
+    String::from_utf8(buf).ok()
+}
+
+#[cfg(not(unix))]
+pub(super) fn hostname() -> Option<String> {
+    env::var("COMPUTERNAME").ok()
+}
+
+#[cfg(unix)]
+fn user_name_and_id() -> (Option<String>, Option<u32>) {
+    use std::ffi::CStr;
+
+    let uid = unsafe { libc::getuid() };
+
+    let mut passwd = unsafe { std::mem::zeroed::<libc::passwd>() };
+    let mut result = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let res =
+        unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+
+    let name = if res == 0 && !result.is_null() && !passwd.pw_name.is_null() {
+        let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+        Some(name.to_string_lossy().to_string())
+    } else {
+        env::var("USER").ok()
+    };
+
+    (name, Some(uid))
+}
+
+#[cfg(not(unix))]
+fn user_name_and_id() -> (Option<String>, Option<u32>) {
+    (env::var("USERNAME").ok(), None)
+}
+
+pub(super) fn create_user_table(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+    let (name, uid) = user_name_and_id();
+    let home = UserDirs::new().map(|dirs| dirs.home_dir().to_string_lossy().to_string());
+    TableBuilder::new(lua)?
+        .with_value("name", name)?
+        .with_value("uid", uid)?
+        .with_value("home", home)?
+        .build_readonly()
+}
+
+pub(super) fn process_uptime(_: &Lua, _: ()) -> LuaResult<f64> {
+    Ok(START_TIME.elapsed().as_secs_f64())
+}
+
+pub(super) fn process_memory_usage(lua: &Lua, _: ()) -> LuaResult<LuaTable<'_>> {
+    TableBuilder::new(lua)?
+        .with_value("rss", resident_set_size())?
+        .with_value("maxRss", max_resident_set_size())?
+        .with_value("luaHeap", lua.used_memory())?
+        .build_readonly()
+}
+
+#[cfg(target_os = "linux")]
+fn resident_set_size() -> Option<u64> {
+    // NOTE: The second value in statm is the resident set size, in pages
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).ok()?;
+    Some(pages * page_size)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_set_size() -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+fn max_resident_set_size() -> Option<u64> {
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let max_rss = u64::try_from(usage.ru_maxrss).ok()?;
+    // NOTE: Max RSS is given in kilobytes on Linux, but in bytes on macOS
+    if cfg!(target_os = "macos") {
+        Some(max_rss)
+    } else {
+        Some(max_rss * 1024)
+    }
+}
+
+#[cfg(not(unix))]
+fn max_resident_set_size() -> Option<u64> {
+    None
+}
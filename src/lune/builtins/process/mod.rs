@@ -1,5 +1,7 @@
 use std::{
+    collections::HashMap,
     env::{self, consts},
+    ffi::OsString,
     path,
     process::Stdio,
 };
@@ -7,27 +9,44 @@ use std::{
 use mlua::prelude::*;
 use mlua_luau_scheduler::{Functions, LuaSpawnExt};
 use os_str_bytes::RawOsString;
-use tokio::io::AsyncWriteExt;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::{Child, Command},
+    task,
+};
 
-use crate::lune::util::{paths::CWD, TableBuilder};
+use crate::lune::util::{
+    paths::{runtime_cwd, set_runtime_cwd},
+    TableBuilder,
+};
 
+mod info;
+mod rusage;
+mod stream;
 mod tee_writer;
 
+mod child;
+use child::ChildProcess;
+
+mod signal;
+use signal::ProcessSignal;
+
+mod signal_handler;
+use signal_handler::connect_signal_handler;
+
 mod options;
-use options::ProcessSpawnOptions;
+use options::{ProcessSpawnOptions, ProcessSpawnOptionsDetached, ProcessSpawnOptionsStdioKind};
+
+mod pty;
+use pty::{Pty, PtySize};
+
+mod pipeline;
+use pipeline::{spawn_pipeline, ProcessPipelineCommand};
 
 mod wait_for_child;
 use wait_for_child::{wait_for_child, WaitForChildResult};
 
 pub fn create(lua: &Lua) -> LuaResult<LuaTable> {
-    let cwd_str = {
-        let cwd_str = CWD.to_string_lossy().to_string();
-        if !cwd_str.ends_with(path::MAIN_SEPARATOR) {
-            format!("{cwd_str}{}", path::MAIN_SEPARATOR)
-        } else {
-            cwd_str
-        }
-    };
     // Create constants for OS & processor architecture
     let os = lua.create_string(&consts::OS.to_lowercase())?;
     let arch = lua.create_string(&consts::ARCH.to_lowercase())?;
@@ -57,13 +76,141 @@ pub fn create(lua: &Lua) -> LuaResult<LuaTable> {
         .with_value("os", os)?
         .with_value("arch", arch)?
         .with_value("args", args_tab)?
-        .with_value("cwd", cwd_str)?
         .with_value("env", env_tab)?
+        .with_value("pid", std::process::id())?
+        .with_value("ppid", info::parent_pid())?
+        .with_value("executable", info::executable())?
+        .with_value("hostname", info::hostname())?
+        .with_value("user", info::create_user_table(lua)?)?
+        .with_function("uptime", info::process_uptime)?
+        .with_function("memoryUsage", info::process_memory_usage)?
         .with_value("exit", process_exit)?
         .with_async_function("spawn", process_spawn)?
+        .with_function("create", process_create)?
+        .with_async_function("pipeline", process_pipeline)?
+        .with_function("onSignal", process_on_signal)?
+        .with_function("setCwd", process_set_cwd)?
+        .with_function("snapshotEnv", process_snapshot_env)?
+        .with_function("restoreEnv", process_restore_env)?
+        .with_metatable(
+            TableBuilder::new(lua)?
+                .with_function(LuaMetaMethod::Index.name(), process_index)?
+                .build_readonly()?,
+        )?
         .build_readonly()
 }
 
+/*
+    NOTE: The working directory may be changed using `process.setCwd`,
+    so we can't store it as a plain value and look it up on access instead
+*/
+fn process_index<'lua>(
+    lua: &'lua Lua,
+    (_, key): (LuaValue<'lua>, String),
+) -> LuaResult<LuaValue<'lua>> {
+    match key.as_str() {
+        "cwd" => {
+            let mut cwd_str = runtime_cwd(lua).to_string_lossy().to_string();
+            if !cwd_str.ends_with(path::MAIN_SEPARATOR) {
+                cwd_str.push(path::MAIN_SEPARATOR);
+            }
+            cwd_str.into_lua(lua)
+        }
+        _ => Ok(LuaValue::Nil),
+    }
+}
+
+fn process_set_cwd(lua: &Lua, path: String) -> LuaResult<()> {
+    set_runtime_cwd(lua, &path).map_err(|e| {
+        LuaError::RuntimeError(format!("Failed to set working directory to '{path}'\n{e}"))
+    })
+}
+
+/*
+    NOTE: Snapshots must contain exactly the environment variables that can be
+    restored from them, and restoring must only remove variables that could have
+    been in a snapshot - on unix, where variables are arbitrary bytes, this is all
+    of them, but on other platforms only variables that are valid UTF-8 are used
+*/
+fn process_snapshot_env(lua: &Lua, _: ()) -> LuaResult<LuaTable<'_>> {
+    let snapshot = lua.create_table()?;
+    for (key, value) in env::vars_os() {
+        if let (Some(key), Some(value)) = (env_bytes_from_os(key), env_bytes_from_os(value)) {
+            snapshot.set(lua.create_string(key)?, lua.create_string(value)?)?;
+        }
+    }
+    Ok(snapshot)
+}
+
+fn process_restore_env(_: &Lua, snapshot: LuaTable) -> LuaResult<()> {
+    let mut vars = HashMap::new();
+    for pair in snapshot.pairs::<LuaString, LuaString>() {
+        let (key, value) = pair?;
+        validate_env_var(key.as_bytes(), Some(value.as_bytes()))?;
+        vars.insert(
+            env_os_from_bytes(key.as_bytes())?,
+            env_os_from_bytes(value.as_bytes())?,
+        );
+    }
+    for (key, _) in env::vars_os() {
+        if !vars.contains_key(&key) && env_bytes_from_os(key.clone()).is_some() {
+            env::remove_var(key);
+        }
+    }
+    for (key, value) in vars {
+        env::set_var(key, value);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn env_bytes_from_os(s: OsString) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStringExt;
+    Some(s.into_vec())
+}
+
+#[cfg(not(unix))]
+fn env_bytes_from_os(s: OsString) -> Option<Vec<u8>> {
+    s.into_string().ok().map(String::into_bytes)
+}
+
+#[cfg(unix)]
+fn env_os_from_bytes(bytes: &[u8]) -> LuaResult<OsString> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes.to_vec()))
+}
+
+#[cfg(not(unix))]
+fn env_os_from_bytes(bytes: &[u8]) -> LuaResult<OsString> {
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(s) => Ok(OsString::from(s)),
+        Err(_) => Err(LuaError::RuntimeError(
+            "Environment variables must be valid UTF-8 on this platform".to_string(),
+        )),
+    }
+}
+
+// Make sure key and value are valid, otherwise set_var will panic
+fn validate_env_var(key: &[u8], value: Option<&[u8]>) -> LuaResult<()> {
+    if key.is_empty() {
+        Err(LuaError::RuntimeError("Key must not be empty".to_string()))
+    } else if key.contains(&b'=') {
+        Err(LuaError::RuntimeError(
+            "Key must not contain the equals character '='".to_string(),
+        ))
+    } else if key.contains(&b'\0') {
+        Err(LuaError::RuntimeError(
+            "Key must not contain the NUL character".to_string(),
+        ))
+    } else if value.is_some_and(|value| value.contains(&b'\0')) {
+        Err(LuaError::RuntimeError(
+            "Value must not contain the NUL character".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 fn process_env_get<'lua>(
     lua: &'lua Lua,
     (_, key): (LuaValue<'lua>, String),
@@ -83,36 +230,12 @@ fn process_env_set<'lua>(
     _: &'lua Lua,
     (_, key, value): (LuaValue<'lua>, String, Option<String>),
 ) -> LuaResult<()> {
-    // Make sure key is valid, otherwise set_var will panic
-    if key.is_empty() {
-        Err(LuaError::RuntimeError("Key must not be empty".to_string()))
-    } else if key.contains('=') {
-        Err(LuaError::RuntimeError(
-            "Key must not contain the equals character '='".to_string(),
-        ))
-    } else if key.contains('\0') {
-        Err(LuaError::RuntimeError(
-            "Key must not contain the NUL character".to_string(),
-        ))
-    } else {
-        match value {
-            Some(value) => {
-                // Make sure value is valid, otherwise set_var will panic
-                if value.contains('\0') {
-                    Err(LuaError::RuntimeError(
-                        "Value must not contain the NUL character".to_string(),
-                    ))
-                } else {
-                    env::set_var(&key, &value);
-                    Ok(())
-                }
-            }
-            None => {
-                env::remove_var(&key);
-                Ok(())
-            }
-        }
+    validate_env_var(key.as_bytes(), value.as_deref().map(str::as_bytes))?;
+    match value {
+        Some(value) => env::set_var(&key, &value),
+        None => env::remove_var(&key),
     }
+    Ok(())
 }
 
 fn process_env_iter<'lua>(
@@ -142,25 +265,52 @@ async fn process_spawn(
         .await
         .expect("Failed to receive result of spawned process");
 
-    /*
-        NOTE: If an exit code was not given by the child process,
-        we default to 1 if it yielded any error output, otherwise 0
+    res.into_lua_table(lua)
+}
+
+async fn process_pipeline(
+    lua: &Lua,
+    (commands, options): (Vec<ProcessPipelineCommand>, ProcessSpawnOptions),
+) -> LuaResult<LuaTable<'_>> {
+    let res = lua.spawn(spawn_pipeline(commands, options)).await?;
+    res.into_lua_table(lua)
+}
+
+fn process_on_signal<'lua>(
+    lua: &'lua Lua,
+    (signal, callback): (ProcessSignal, LuaFunction<'lua>),
+) -> LuaResult<LuaTable<'lua>> {
+    connect_signal_handler(lua, signal, callback)
+}
 
-        An exit code may be missing if the process was terminated by
-        some external signal, which is the only time we use this default
+fn process_create(
+    lua: &Lua,
+    (program, args, mut options): (String, Option<Vec<String>>, ProcessSpawnOptions),
+) -> LuaResult<ChildProcess> {
+    /*
+        NOTE: Stdio options are not used here, all streams are always
+        piped and exposed on the child process handle for reading & writing,
+        and neither is the timeout, so there is no process group to kill either
     */
-    let code = res.status.code().unwrap_or(match res.stderr.is_empty() {
-        true => 0,
-        false => 1,
-    });
+    options.kill_process_group = false;
+    if options.detached.is_some() {
+        return Err(LuaError::runtime(
+            "Option 'detached' is not supported for process.create",
+        ));
+    }
+    if let Some(size) = options.pty {
+        let (pty, child) = spawn_pty_command(program, args, options, size)?;
+        return ChildProcess::new_pty(lua, child, pty).into_lua_err();
+    }
 
-    // Construct and return a readonly lua table with results
-    TableBuilder::new(lua)?
-        .with_value("ok", code == 0)?
-        .with_value("code", code)?
-        .with_value("stdout", lua.create_string(&res.stdout)?)?
-        .with_value("stderr", lua.create_string(&res.stderr)?)?
-        .build_readonly()
+    let child = options
+        .into_command(program, args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    Ok(ChildProcess::new(lua, child))
 }
 
 async fn spawn_command(
@@ -168,9 +318,18 @@ async fn spawn_command(
     args: Option<Vec<String>>,
     mut options: ProcessSpawnOptions,
 ) -> LuaResult<WaitForChildResult> {
+    if let Some(detached) = options.detached.clone() {
+        return spawn_detached(program, args, options, &detached);
+    }
+    if let Some(size) = options.pty {
+        return spawn_pty(program, args, options, size).await;
+    }
+
     let stdout = options.stdio.stdout;
     let stderr = options.stdio.stderr;
     let stdin = options.stdio.stdin.take();
+    let timeout = options.timeout;
+    let kill_process_group = options.kill_process_group;
 
     let mut child = options
         .into_command(program, args)
@@ -187,5 +346,93 @@ async fn spawn_command(
         child_stdin.write_all(&stdin).await.into_lua_err()?;
     }
 
-    wait_for_child(child, stdout, stderr).await
+    wait_for_child(child, stdout, stderr, timeout, kill_process_group).await
+}
+
+fn spawn_detached(
+    program: String,
+    args: Option<Vec<String>>,
+    options: ProcessSpawnOptions,
+    detached: &ProcessSpawnOptionsDetached,
+) -> LuaResult<WaitForChildResult> {
+    /*
+        NOTE: The child is dropped right away without being killed, and
+        tokio will take care of reaping it in the background if it exits
+        while Lune is still running, so that it does not become a zombie
+    */
+    let child = options
+        .into_command(program, args)
+        .stdin(Stdio::null())
+        .stdout(detached.stdout()?)
+        .stderr(detached.stderr()?)
+        .spawn()?;
+
+    let pid = child.id().expect("Child process was already reaped");
+    Ok(WaitForChildResult::detached(pid))
+}
+
+fn spawn_pty_command(
+    program: String,
+    args: Option<Vec<String>>,
+    options: ProcessSpawnOptions,
+    size: PtySize,
+) -> LuaResult<(Pty, Child)> {
+    let (pty, slave) = Pty::open(size)?;
+
+    let mut cmd = options.into_std_command(program, args);
+    Pty::configure_command(&mut cmd, &slave)?;
+    let child = Command::from(cmd).spawn()?;
+
+    /*
+        NOTE: We must not keep our own handle to the side of the pty used by
+        the child around, since reading from the pty only ends once all handles
+        to that side have been closed, meaning after the child process has exited
+    */
+    drop(slave);
+
+    Ok((pty, child))
+}
+
+const PTY_VEOF: &[u8] = b"\x04";
+
+async fn spawn_pty(
+    program: String,
+    args: Option<Vec<String>>,
+    mut options: ProcessSpawnOptions,
+    size: PtySize,
+) -> LuaResult<WaitForChildResult> {
+    let stdin = options.stdio.stdin.take();
+    let timeout = options.timeout;
+    let kill_process_group = options.kill_process_group;
+
+    let (pty, child) = spawn_pty_command(program, args, options, size)?;
+
+    let mut reader = pty.reader()?;
+    let output_task = task::spawn(async move {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).await.map(|_| output)
+    });
+
+    /*
+        NOTE: Input is written in a separate task, since the child may not read all of it
+        before writing output of its own, and the pty buffers are limited in size - the input
+        is then ended using VEOF, which needs to be written twice if the last line is unfinished
+    */
+    if let Some(stdin) = stdin {
+        let mut writer = pty.writer()?;
+        task::spawn(async move {
+            writer.write_all(&stdin).await?;
+            if !stdin.is_empty() && !stdin.ends_with(b"\n") {
+                writer.write_all(PTY_VEOF).await?;
+            }
+            writer.write_all(PTY_VEOF).await?;
+            writer.flush().await
+        });
+    }
+
+    let none = ProcessSpawnOptionsStdioKind::None;
+    let mut res = wait_for_child(child, none, none, timeout, kill_process_group).await?;
+    res.stdout = output_task.await.into_lua_err()??;
+
+    Ok(res)
 }
@@ -0,0 +1,69 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use mlua::prelude::*;
+
+use crate::lune::util::paths::resolve_runtime_path;
+
+/**
+    Options for detached child processes, which are given
+    the paths of files to write their output streams to.
+
+    Streams without a file path are redirected to the null device, and relative
+    file paths are resolved using the current working directory of the runtime.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ProcessSpawnOptionsDetached {
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+}
+
+impl ProcessSpawnOptionsDetached {
+    pub fn stdout(&self) -> io::Result<Stdio> {
+        open_stdio(self.stdout.as_deref())
+    }
+
+    pub fn stderr(&self) -> io::Result<Stdio> {
+        open_stdio(self.stderr.as_deref())
+    }
+}
+
+// NOTE: Files are opened in append mode, so both streams
+// may be written to the same file without overwriting
+fn open_stdio(path: Option<&Path>) -> io::Result<Stdio> {
+    match path {
+        None => Ok(Stdio::null()),
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            Ok(Stdio::from(file))
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for ProcessSpawnOptionsDetached {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Boolean(true) => Ok(Self::default()),
+            LuaValue::Table(t) => Ok(Self {
+                stdout: t
+                    .get::<_, Option<String>>("stdout")?
+                    .map(|path| resolve_runtime_path(lua, path)),
+                stderr: t
+                    .get::<_, Option<String>>("stderr")?
+                    .map(|path| resolve_runtime_path(lua, path)),
+            }),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessSpawnOptionsDetached",
+                message: Some(format!(
+                    "Invalid spawn options detached - expected 'true' or table, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
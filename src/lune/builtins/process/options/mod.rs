@@ -1,16 +1,25 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
     env::{self},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process::Command as StdCommand,
+    time::Duration,
 };
 
 use directories::UserDirs;
 use mlua::prelude::*;
 use tokio::process::Command;
 
+use crate::lune::util::paths::{resolve_runtime_path, runtime_cwd};
+
+use super::pty::PtySize;
+
+mod detached;
 mod kind;
 mod stdio;
 
+pub(super) use detached::*;
 pub(super) use kind::*;
 pub(super) use stdio::*;
 
@@ -20,13 +29,20 @@ pub(super) struct ProcessSpawnOptions {
     pub envs: HashMap<String, String>,
     pub shell: Option<String>,
     pub stdio: ProcessSpawnOptionsStdio,
+    pub timeout: Option<Duration>,
+    pub kill_process_group: bool,
+    pub detached: Option<ProcessSpawnOptionsDetached>,
+    pub pty: Option<PtySize>,
 }
 
 impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
-    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
         let mut this = Self::default();
         let value = match value {
-            LuaValue::Nil => return Ok(this),
+            LuaValue::Nil => {
+                this.cwd = Some(runtime_cwd(lua));
+                return Ok(this);
+            }
             LuaValue::Table(t) => t,
             _ => {
                 return Err(LuaError::FromLuaConversionError {
@@ -44,10 +60,14 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
             If we got a working directory to use:
 
             1. Substitute leading tilde (~) for the users home dir
-            2. Make sure it exists
+            2. Resolve it relative to the working directory of the runtime
+            3. Make sure it exists
+
+            Otherwise, use the working directory of the runtime, which may
+            have been changed using `process.setCwd` and differ from our own
         */
         match value.get("cwd")? {
-            LuaValue::Nil => {}
+            LuaValue::Nil => this.cwd = Some(runtime_cwd(lua)),
             LuaValue::String(s) => {
                 let mut cwd = PathBuf::from(s.to_str()?);
                 if let Ok(stripped) = cwd.strip_prefix("~") {
@@ -58,6 +78,7 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
                     })?;
                     cwd = user_dirs.home_dir().join(stripped)
                 }
+                let cwd = resolve_runtime_path(lua, cwd);
                 if !cwd.exists() {
                     return Err(LuaError::runtime(
                         "Invalid value for option 'cwd' - path does not exist",
@@ -120,8 +141,16 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
             If we got options for stdio handling, parse those as well - note that
             we accept a separate "stdin" value here for compatibility with older
             scripts, but the user should preferrably pass it in the stdio table
+
+            The special "pty" stdio kind connects all streams to a pseudo-terminal
+            instead, which may optionally be given a size using "ptySize"
         */
-        this.stdio = value.get("stdio")?;
+        match value.get("stdio")? {
+            LuaValue::String(s) if s.to_str()?.trim().eq_ignore_ascii_case("pty") => {
+                this.pty = Some(value.get("ptySize")?);
+            }
+            stdio => this.stdio = ProcessSpawnOptionsStdio::from_lua(stdio, lua)?,
+        }
         match value.get("stdin")? {
             LuaValue::Nil => {}
             LuaValue::String(s) => this.stdio.stdin = Some(s.as_bytes().to_vec()),
@@ -133,20 +162,110 @@ impl<'lua> FromLua<'lua> for ProcessSpawnOptions {
             }
         }
 
+        /*
+            If we got a timeout, make sure it is a positive number of seconds
+        */
+        match value.get("timeout")? {
+            LuaValue::Nil => {}
+            LuaValue::Integer(i) if i > 0 => this.timeout = Some(Duration::from_secs(i as u64)),
+            LuaValue::Number(n) if n.is_finite() && n > 0.0 => {
+                this.timeout = Some(Duration::try_from_secs_f64(n).map_err(|_| {
+                    LuaError::runtime("Invalid value for option 'timeout' - number is too large")
+                })?);
+            }
+            LuaValue::Integer(_) | LuaValue::Number(_) => {
+                return Err(LuaError::runtime(
+                    "Invalid value for option 'timeout' - must be a positive number of seconds",
+                ))
+            }
+            value => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'timeout' - expected 'number', got '{}'",
+                    value.type_name()
+                )))
+            }
+        }
+
+        /*
+            If we should kill the entire process group of the child on timeout,
+            note that this is only supported on unix platforms and the child
+            will be started in a new process group for this to be possible
+        */
+        match value.get("killProcessGroup")? {
+            LuaValue::Nil => {}
+            LuaValue::Boolean(b) => this.kill_process_group = b,
+            value => {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid type for option 'killProcessGroup' - expected 'boolean', got '{}'",
+                    value.type_name()
+                )))
+            }
+        }
+
+        /*
+            If we should detach the child process, it will be started in a new
+            session and will not be waited on, meaning it may outlive Lune - this
+            can not be combined with any options that need to wait for the child
+        */
+        match value.get("detached")? {
+            LuaValue::Nil | LuaValue::Boolean(false) => {}
+            value => this.detached = Some(ProcessSpawnOptionsDetached::from_lua(value, lua)?),
+        }
+        if this.pty.is_none() && !value.get::<_, LuaValue>("ptySize")?.is_nil() {
+            return Err(LuaError::runtime(
+                "Invalid value for option 'ptySize' - can only be used with 'pty' stdio",
+            ));
+        }
+        if this.detached.is_some() {
+            if this.pty.is_some() {
+                return Err(LuaError::runtime(
+                    "Invalid value for option 'stdio' - 'pty' can not be used with 'detached'",
+                ));
+            }
+            if this.timeout.is_some() {
+                return Err(LuaError::runtime(
+                    "Invalid value for option 'timeout' - can not be used with 'detached'",
+                ));
+            }
+            if this.stdio.stdin.is_some() {
+                return Err(LuaError::runtime(
+                    "Invalid value for option 'stdin' - can not be used with 'detached'",
+                ));
+            }
+        }
+
         Ok(this)
     }
 }
 
 impl ProcessSpawnOptions {
     pub fn into_command(self, program: impl Into<String>, args: Option<Vec<String>>) -> Command {
+        Command::from(self.into_std_command(program, args))
+    }
+
+    pub fn into_std_command(
+        self,
+        program: impl Into<String>,
+        args: Option<Vec<String>>,
+    ) -> StdCommand {
         let mut program = program.into();
 
-        // Run a shell using the command param if wanted
+        /*
+            Run a shell using the command param if wanted - note that the program
+            is passed to the shell as-is so that it may contain a full shell command,
+            but any args are escaped so that they are always passed through literally
+        */
         let pargs = match self.shell {
             None => args,
             Some(shell) => {
                 let shell_args = match args {
-                    Some(args) => vec!["-c".to_string(), format!("{} {}", program, args.join(" "))],
+                    Some(args) => {
+                        let escaped = args.iter().map(|arg| escape_shell_arg(&shell, arg));
+                        vec![
+                            "-c".to_string(),
+                            format!("{} {}", program, escaped.collect::<Vec<_>>().join(" ")),
+                        ]
+                    }
                     None => vec!["-c".to_string(), program.to_string()],
                 };
                 program = shell.to_string();
@@ -156,9 +275,9 @@ impl ProcessSpawnOptions {
 
         // Create command with the wanted options
         let mut cmd = match pargs {
-            None => Command::new(program),
+            None => StdCommand::new(program),
             Some(args) => {
-                let mut cmd = Command::new(program);
+                let mut cmd = StdCommand::new(program);
                 cmd.args(args);
                 cmd
             }
@@ -172,6 +291,53 @@ impl ProcessSpawnOptions {
             cmd.envs(self.envs);
         }
 
+        // Start detached processes in a new session, without a controlling
+        // terminal, and others in a new process group if they should be killable
+        // as a whole on timeout - note that a new session is also a new process
+        // group, and that processes connected to a pty get their own session later.
+        // A new process group would stop the child from receiving Ctrl+C from the
+        // terminal, so it is only created when a timeout may need to kill it
+        #[cfg(unix)]
+        if self.detached.is_some() {
+            use std::os::unix::process::CommandExt;
+            unsafe {
+                cmd.pre_exec(|| match libc::setsid() {
+                    -1 => Err(std::io::Error::last_os_error()),
+                    _ => Ok(()),
+                });
+            }
+        } else if self.kill_process_group && self.timeout.is_some() && self.pty.is_none() {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
+        }
+
+        #[cfg(windows)]
+        if self.detached.is_some() {
+            use std::os::windows::process::CommandExt;
+            const DETACHED_PROCESS: u32 = 0x0000_0008;
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+            cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+        }
+
         cmd
     }
 }
+
+fn escape_shell_arg<'a>(shell: &str, arg: &'a str) -> Cow<'a, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return Cow::Borrowed(arg);
+    }
+
+    let shell_name = Path::new(shell)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    // Both posix shells and powershell treat everything inside of single
+    // quotes literally, but they differ in how to escape single quotes
+    Cow::Owned(match shell_name.as_str() {
+        "powershell" | "pwsh" => format!("'{}'", arg.replace('\'', "''")),
+        _ => format!("'{}'", arg.replace('\'', r"'\''")),
+    })
+}
@@ -0,0 +1,189 @@
+use std::process::Stdio;
+
+use futures_util::future::join_all;
+use mlua::prelude::*;
+use tokio::{io::AsyncWriteExt, process::Child, task};
+
+use crate::lune::util::TableBuilder;
+
+use super::{
+    options::{ProcessSpawnOptions, ProcessSpawnOptionsStdioKind},
+    wait_for_child::{wait_for_child, WaitForChildResult},
+};
+
+/**
+    A single command in a pipeline, given from Lua as `{ program, args? }`.
+*/
+#[derive(Debug, Clone)]
+pub(super) struct ProcessPipelineCommand {
+    pub program: String,
+    pub args: Option<Vec<String>>,
+}
+
+impl<'lua> FromLua<'lua> for ProcessPipelineCommand {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Table(t) => Ok(Self {
+                program: t.get(1)?,
+                args: t.get(2)?,
+            }),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessPipelineCommand",
+                message: Some(format!(
+                    "Invalid pipeline command - expected table, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    Results for all commands in a pipeline, in the same order as the commands were given.
+*/
+#[derive(Debug, Clone)]
+pub(super) struct PipelineResult {
+    pub results: Vec<WaitForChildResult>,
+}
+
+impl PipelineResult {
+    pub fn into_lua_table(self, lua: &Lua) -> LuaResult<LuaTable<'_>> {
+        /*
+            NOTE: We follow the same semantics as `set -o pipefail` in shells here,
+            the pipeline is only successful if all of its commands were successful,
+            and its exit code is the exit code of the last failed command, if any
+        */
+        let ok = self.results.iter().all(WaitForChildResult::is_ok);
+        let failed = self.results.iter().rposition(|res| !res.is_ok());
+        let timed_out = self.results.iter().any(|res| res.timed_out);
+
+        let stdout = self
+            .results
+            .last()
+            .map(|res| res.stdout.clone())
+            .unwrap_or_default();
+        let stderr = self
+            .results
+            .iter()
+            .flat_map(|res| res.stderr.iter().copied())
+            .collect::<Vec<_>>();
+
+        let results = self
+            .results
+            .into_iter()
+            .map(|res| res.into_lua_table(lua))
+            .collect::<LuaResult<Vec<_>>>()?;
+
+        let code_index = failed.unwrap_or(results.len() - 1);
+        let code = results[code_index].get::<_, i32>("code")?;
+
+        TableBuilder::new(lua)?
+            .with_value("ok", ok)?
+            .with_value("code", code)?
+            .with_value("stdout", lua.create_string(stdout)?)?
+            .with_value("stderr", lua.create_string(stderr)?)?
+            .with_value("timedOut", timed_out)?
+            .with_value("results", lua.create_sequence_from(results)?)?
+            .build_readonly()
+    }
+}
+
+pub(super) async fn spawn_pipeline(
+    commands: Vec<ProcessPipelineCommand>,
+    mut options: ProcessSpawnOptions,
+) -> LuaResult<PipelineResult> {
+    if commands.is_empty() {
+        return Err(LuaError::runtime(
+            "Pipeline must contain at least one command",
+        ));
+    }
+    if options.detached.is_some() {
+        return Err(LuaError::runtime(
+            "Option 'detached' is not supported for process.pipeline",
+        ));
+    }
+    if options.pty.is_some() {
+        return Err(LuaError::runtime(
+            "Stdio 'pty' is not supported for process.pipeline",
+        ));
+    }
+
+    let stdout = options.stdio.stdout;
+    let stderr = options.stdio.stderr;
+    let stdin = options.stdio.stdin.take();
+    let timeout = options.timeout;
+    let kill_process_group = options.kill_process_group;
+
+    /*
+        Spawn all of the commands, connecting the stdout of each
+        command directly to the stdin of the next one, meaning the
+        output in between commands never passes through Lune itself
+    */
+    let last_index = commands.len() - 1;
+    let mut children: Vec<Child> = Vec::with_capacity(commands.len());
+    for (index, command) in commands.into_iter().enumerate() {
+        let spawned = (|| -> LuaResult<Child> {
+            let child_stdin = match children.last_mut() {
+                Some(prev) => prev
+                    .stdout
+                    .take()
+                    .expect("stdout must be piped for commands in a pipeline")
+                    .try_into()
+                    .into_lua_err()?,
+                None if stdin.is_some() => Stdio::piped(),
+                None => Stdio::null(),
+            };
+            let child_stdout = if index == last_index {
+                stdout.as_stdio()
+            } else {
+                Stdio::piped()
+            };
+            let child = options
+                .clone()
+                .into_command(command.program, command.args)
+                .stdin(child_stdin)
+                .stdout(child_stdout)
+                .stderr(stderr.as_stdio())
+                .spawn()?;
+            Ok(child)
+        })();
+        match spawned {
+            Ok(child) => children.push(child),
+            Err(e) => {
+                // NOTE: Commands that were already spawned would otherwise
+                // keep running in the background, without anything reading
+                // their output, so we make sure that they have exited first
+                for child in &mut children {
+                    child.start_kill().ok();
+                }
+                for child in &mut children {
+                    child.wait().await.ok();
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    // Write any stdin to the first command in the background, then close it
+    if let Some(stdin) = stdin {
+        let mut child_stdin = children[0].stdin.take().unwrap();
+        task::spawn(async move { child_stdin.write_all(&stdin).await });
+    }
+
+    // NOTE: Waiting for a child process is what makes sure it exits, so even if waiting
+    // for one of them fails, we must keep waiting for the rest before giving the error
+    let results = join_all(children.into_iter().enumerate().map(|(index, child)| {
+        let stdout_kind = if index == last_index {
+            stdout
+        } else {
+            ProcessSpawnOptionsStdioKind::None
+        };
+        wait_for_child(child, stdout_kind, stderr, timeout, kill_process_group)
+    }))
+    .await
+    .into_iter()
+    .collect::<LuaResult<Vec<_>>>()?;
+
+    Ok(PipelineResult { results })
+}
@@ -0,0 +1,260 @@
+use std::{
+    fs::File,
+    io,
+    pin::Pin,
+    process::{Command as StdCommand, Stdio},
+    task::{Context, Poll},
+};
+
+use mlua::prelude::*;
+use tokio::io::{AsyncRead, ReadBuf};
+
+const DEFAULT_ROWS: u16 = 24;
+const DEFAULT_COLS: u16 = 80;
+
+/**
+    The size of a pseudo-terminal, in rows and columns of characters.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PtySize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+impl Default for PtySize {
+    fn default() -> Self {
+        Self {
+            rows: DEFAULT_ROWS,
+            cols: DEFAULT_COLS,
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for PtySize {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::Table(t) => {
+                let rows: Option<u16> = t.get("rows")?;
+                let cols: Option<u16> = t.get("cols")?;
+                let size = Self {
+                    rows: rows.unwrap_or(DEFAULT_ROWS),
+                    cols: cols.unwrap_or(DEFAULT_COLS),
+                };
+                if size.rows == 0 || size.cols == 0 {
+                    return Err(LuaError::runtime(
+                        "Invalid pty size - rows and cols must be positive",
+                    ));
+                }
+                Ok(size)
+            }
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "PtySize",
+                message: Some(format!(
+                    "Invalid pty size - expected table, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    The controlling side of a pseudo-terminal, which child processes
+    are connected to using the other side of it as all of their stdio.
+
+    Output from the child process can be read from it, and any
+    input written to it is given to the child process as stdin.
+*/
+#[derive(Debug)]
+pub struct Pty {
+    master: File,
+}
+
+#[cfg(unix)]
+impl Pty {
+    /**
+        Opens a new pseudo-terminal with the given size, returning
+        both the controlling side and the side for the child process.
+    */
+    pub fn open(size: PtySize) -> io::Result<(Self, File)> {
+        use std::{fs::OpenOptions, os::unix::prelude::*};
+
+        let master = unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            if fd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            File::from_raw_fd(fd)
+        };
+
+        let fd = master.as_raw_fd();
+        unsafe {
+            if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) == -1
+                || libc::grantpt(fd) == -1
+                || libc::unlockpt(fd) == -1
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(slave_name(fd)?)?;
+
+        let pty = Self { master };
+        pty.resize(size)?;
+        Ok((pty, slave))
+    }
+
+    /**
+        Resizes the pseudo-terminal, which also sends `SIGWINCH` to the child process.
+    */
+    pub fn resize(&self, size: PtySize) -> io::Result<()> {
+        use std::os::unix::prelude::*;
+
+        let winsize = libc::winsize {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let res = unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) };
+        if res == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+        Configures the given command to use the given side of a
+        pseudo-terminal as its stdio, and as its controlling terminal.
+    */
+    pub fn configure_command(cmd: &mut StdCommand, slave: &File) -> io::Result<()> {
+        use std::os::unix::process::CommandExt;
+
+        cmd.stdin(Stdio::from(slave.try_clone()?));
+        cmd.stdout(Stdio::from(slave.try_clone()?));
+        cmd.stderr(Stdio::from(slave.try_clone()?));
+
+        // NOTE: The child process must be in a new session for the
+        // pseudo-terminal to be able to become its controlling terminal
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+impl Pty {
+    pub fn open(_: PtySize) -> io::Result<(Self, File)> {
+        Err(unsupported_error())
+    }
+
+    pub fn resize(&self, _: PtySize) -> io::Result<()> {
+        Err(unsupported_error())
+    }
+
+    pub fn configure_command(_: &mut StdCommand, _: &File) -> io::Result<()> {
+        Err(unsupported_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn unsupported_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Pseudo-terminals are not supported on this platform",
+    )
+}
+
+impl Pty {
+    /**
+        Creates a new reader for output from the pseudo-terminal.
+    */
+    pub fn reader(&self) -> io::Result<PtyReader> {
+        let file = tokio::fs::File::from_std(self.master.try_clone()?);
+        Ok(PtyReader { file })
+    }
+
+    /**
+        Creates a new writer for input to the pseudo-terminal.
+    */
+    pub fn writer(&self) -> io::Result<tokio::fs::File> {
+        Ok(tokio::fs::File::from_std(self.master.try_clone()?))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn slave_name(fd: std::os::unix::io::RawFd) -> io::Result<std::path::PathBuf> {
+    use std::{ffi::CStr, os::unix::ffi::OsStrExt};
+
+    let mut buf = vec![0 as libc::c_char; 128];
+    let res = unsafe { libc::ptsname_r(fd, buf.as_mut_ptr(), buf.len()) };
+    if res != 0 {
+        return Err(io::Error::from_raw_os_error(res));
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Ok(std::ffi::OsStr::from_bytes(name.to_bytes()).into())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn slave_name(fd: std::os::unix::io::RawFd) -> io::Result<std::path::PathBuf> {
+    use std::{ffi::CStr, os::unix::ffi::OsStrExt, sync::Mutex};
+
+    // NOTE: ptsname is not thread safe, and ptsname_r is not available everywhere
+    static PTSNAME_LOCK: Mutex<()> = Mutex::new(());
+    let _guard = PTSNAME_LOCK.lock().unwrap();
+
+    let ptr = unsafe { libc::ptsname(fd) };
+    if ptr.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let name = unsafe { CStr::from_ptr(ptr) };
+    Ok(std::ffi::OsStr::from_bytes(name.to_bytes()).into())
+}
+
+/**
+    A reader for output from a pseudo-terminal.
+
+    Reading from a pseudo-terminal gives an error once all child processes connected
+    to it have exited, instead of the usual end of file, which this reader takes care of.
+*/
+pub struct PtyReader {
+    file: tokio::fs::File,
+}
+
+impl AsyncRead for PtyReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.file).poll_read(cx, buf) {
+            Poll::Ready(Err(e)) if is_closed_error(&e) => Poll::Ready(Ok(())),
+            res => res,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_closed_error(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EIO)
+}
+
+#[cfg(not(unix))]
+fn is_closed_error(_: &io::Error) -> bool {
+    false
+}
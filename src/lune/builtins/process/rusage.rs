@@ -0,0 +1,114 @@
+use std::{process::ExitStatus, time::Duration};
+
+use tokio::{io, process::Child};
+
+/**
+    Resource usage of a child process that has exited.
+
+    Currently only collected on Linux, where `waitid` gives us the resource
+    usage of one specific child process, without needing to reap it.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceUsage {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    pub max_rss: u64,
+}
+
+/**
+    Waits for a child process to exit, collecting its resource usage if possible.
+
+    Waiting is cancel-safe, the same waiter may be awaited again after being
+    cancelled, for example when the child process is killed after a timeout.
+*/
+pub struct ChildExitWaiter {
+    #[cfg(target_os = "linux")]
+    handle: tokio::task::JoinHandle<io::Result<ResourceUsage>>,
+    #[cfg(target_os = "linux")]
+    usage: Option<ResourceUsage>,
+}
+
+#[cfg(target_os = "linux")]
+impl ChildExitWaiter {
+    pub fn new(child: &Child) -> Self {
+        #[allow(clippy::cast_possible_wrap)]
+        let pid = child.id().expect("Child process was already reaped") as libc::pid_t;
+        Self {
+            handle: tokio::task::spawn_blocking(move || wait_without_reaping(pid)),
+            usage: None,
+        }
+    }
+
+    /*
+        NOTE: The child process is only reaped by tokio, once we have
+        collected its resource usage, since the usage is gone after reaping
+    */
+    pub async fn wait(
+        &mut self,
+        child: &mut Child,
+    ) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        if self.usage.is_none() {
+            self.usage = Some((&mut self.handle).await??);
+        }
+        Ok((child.wait().await?, self.usage))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl ChildExitWaiter {
+    pub fn new(_: &Child) -> Self {
+        Self {}
+    }
+
+    pub async fn wait(
+        &mut self,
+        child: &mut Child,
+    ) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        Ok((child.wait().await?, None))
+    }
+}
+
+/**
+    Waits for the child process with the given pid to exit, and gets its resource usage.
+
+    The child process is left as a zombie, so that it can still be reaped by tokio. Note that
+    the `waitid` function in libc does not give us resource usage, only the syscall itself does.
+*/
+#[cfg(target_os = "linux")]
+fn wait_without_reaping(pid: libc::pid_t) -> io::Result<ResourceUsage> {
+    let mut info = unsafe { std::mem::zeroed::<libc::siginfo_t>() };
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_waitid,
+                libc::P_PID,
+                pid,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+                &mut usage,
+            )
+        };
+        if res != -1 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    Ok(ResourceUsage {
+        user_time: timeval_to_duration(usage.ru_utime),
+        system_time: timeval_to_duration(usage.ru_stime),
+        // NOTE: Max RSS is given in kilobytes on Linux
+        max_rss: usage.ru_maxrss as u64 * 1024,
+    })
+}
+
+#[cfg(target_os = "linux")]
+#[allow(clippy::cast_sign_loss)]
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+}
@@ -0,0 +1,146 @@
+use std::{fmt, process::ExitStatus, str::FromStr};
+
+use itertools::Itertools;
+use mlua::prelude::*;
+
+/*
+    NOTE: Signal numbers differ between platforms, so we only
+    hardcode the names here and look up numbers using libc
+
+    Windows does not have signals, but we still accept a couple of names
+    there to make it easier to write cross-platform scripts, and treat
+    all of them as a request to forcefully terminate the process
+*/
+
+#[cfg(unix)]
+const SIGNALS: &[(&str, i32)] = &[
+    ("SIGHUP", libc::SIGHUP),
+    ("SIGINT", libc::SIGINT),
+    ("SIGQUIT", libc::SIGQUIT),
+    ("SIGABRT", libc::SIGABRT),
+    ("SIGKILL", libc::SIGKILL),
+    ("SIGUSR1", libc::SIGUSR1),
+    ("SIGUSR2", libc::SIGUSR2),
+    ("SIGPIPE", libc::SIGPIPE),
+    ("SIGALRM", libc::SIGALRM),
+    ("SIGTERM", libc::SIGTERM),
+    ("SIGCHLD", libc::SIGCHLD),
+    ("SIGCONT", libc::SIGCONT),
+    ("SIGSTOP", libc::SIGSTOP),
+    ("SIGTSTP", libc::SIGTSTP),
+    ("SIGWINCH", libc::SIGWINCH),
+];
+
+#[cfg(not(unix))]
+const SIGNALS: &[(&str, i32)] = &[("SIGINT", 2), ("SIGKILL", 9), ("SIGTERM", 15)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessSignal {
+    name: &'static str,
+    number: i32,
+}
+
+impl ProcessSignal {
+    pub fn all() -> impl Iterator<Item = Self> {
+        SIGNALS.iter().map(|&(name, number)| Self { name, number })
+    }
+
+    pub fn from_number(number: i32) -> Option<Self> {
+        Self::all().find(|s| s.number == number)
+    }
+
+    pub fn number(&self) -> i32 {
+        self.number
+    }
+
+    /**
+        If the default action for this signal is to terminate the process.
+
+        Signals that are ignored or that stop / continue the process by default return `false`.
+    */
+    pub fn terminates_by_default(&self) -> bool {
+        !matches!(
+            self.name,
+            "SIGCHLD" | "SIGCONT" | "SIGSTOP" | "SIGTSTP" | "SIGWINCH"
+        )
+    }
+}
+
+/**
+    Gets the number of the signal that terminated a process, if it was terminated by one.
+
+    Always returns `None` on platforms that do not have signals.
+*/
+#[cfg(unix)]
+pub fn exit_signal_number(status: ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+pub fn exit_signal_number(_: ExitStatus) -> Option<i32> {
+    None
+}
+
+/**
+    Gets the exit code of a process, along with the number
+    of the signal that terminated it, if it was terminated by one.
+
+    If an exit code was not given by the process, it was terminated
+    by a signal, and we follow the common shell convention of using
+    128 + the signal number as the exit code in that case.
+*/
+pub fn exit_code_and_signal(status: ExitStatus) -> (i32, Option<i32>) {
+    let signal = exit_signal_number(status);
+    let code = match (status.code(), signal) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    };
+    (code, signal)
+}
+
+impl fmt::Display for ProcessSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+impl FromStr for ProcessSignal {
+    type Err = LuaError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.trim().to_ascii_uppercase();
+        let name = if upper.starts_with("SIG") {
+            upper
+        } else {
+            format!("SIG{upper}")
+        };
+        Self::all().find(|s| s.name == name).ok_or_else(|| {
+            LuaError::RuntimeError(format!(
+                "Invalid signal - got '{}', expected one of {}",
+                s,
+                Self::all().map(|k| format!("'{k}'")).join(", ")
+            ))
+        })
+    }
+}
+
+impl<'lua> FromLua<'lua> for ProcessSignal {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::String(s) => s.to_str()?.parse(),
+            LuaValue::Integer(i) => Self::from_number(i)
+                .ok_or_else(|| LuaError::RuntimeError(format!("Invalid signal number '{i}'"))),
+            LuaValue::Number(n) => Self::from_number(n as i32)
+                .ok_or_else(|| LuaError::RuntimeError(format!("Invalid signal number '{n}'"))),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "ProcessSignal",
+                message: Some(format!(
+                    "Invalid signal - expected string or number, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
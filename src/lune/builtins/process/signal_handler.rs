@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    process::ExitCode,
+    rc::{Rc, Weak},
+    sync::Arc,
+};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSchedulerExt;
+use tokio::{io, sync::Notify};
+
+use crate::lune::util::{threads::ThreadTracker, TableBuilder};
+
+use super::signal::ProcessSignal;
+
+/**
+    Signal handlers that are connected in a single runtime.
+
+    Each signal with at least one connected handler has a single listener
+    task, which calls all of the handlers and stops once they are disconnected.
+*/
+#[derive(Debug, Default)]
+struct SignalHandlers {
+    next_id: usize,
+    signals: HashMap<i32, SignalListener>,
+}
+
+#[derive(Debug)]
+struct SignalListener {
+    handlers: Vec<(usize, LuaRegistryKey)>,
+    stop: Arc<Notify>,
+}
+
+/*
+    NOTE: Once a signal has been listened to, tokio will never restore the
+    default behavior for that signal, meaning a script that disconnects all of
+    its handlers for SIGINT would otherwise no longer exit when given Ctrl+C
+
+    On unix we restore the default disposition ourselves once nothing in the
+    process listens for the signal anymore, and hand it back to tokio when a
+    new listener is created - this is tracked for the entire process, since
+    signal dispositions are, and not just for the runtime creating the listener
+
+    On Windows, tokio already lets Ctrl+C through when nothing is listening for it
+*/
+#[cfg(unix)]
+static OS_HOOKS: once_cell::sync::Lazy<std::sync::Mutex<HashMap<i32, OsHook>>> =
+    once_cell::sync::Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
+
+#[cfg(unix)]
+#[derive(Default)]
+struct OsHook {
+    listeners: usize,
+    // The disposition installed by tokio, while the default one is restored
+    replaced: Option<libc::sigaction>,
+}
+
+#[cfg(unix)]
+struct SignalStream {
+    signal: ProcessSignal,
+    inner: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl SignalStream {
+    fn new(signal: ProcessSignal) -> io::Result<Self> {
+        use tokio::signal::unix::{signal as unix_signal, SignalKind};
+
+        let mut hooks = OS_HOOKS.lock().expect("Signal hooks were poisoned");
+        let hook = hooks.entry(signal.number()).or_default();
+        if let Some(action) = hook.replaced {
+            if unsafe { libc::sigaction(signal.number(), &action, std::ptr::null_mut()) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            hook.replaced = None;
+        }
+
+        let inner = unix_signal(SignalKind::from_raw(signal.number()))?;
+        hook.listeners += 1;
+        Ok(Self { signal, inner })
+    }
+
+    async fn recv(&mut self) -> Option<()> {
+        self.inner.recv().await
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SignalStream {
+    fn drop(&mut self) {
+        let mut hooks = OS_HOOKS.lock().expect("Signal hooks were poisoned");
+        let hook = hooks
+            .get_mut(&self.signal.number())
+            .expect("Signal hook was not created for stream");
+        hook.listeners -= 1;
+        if hook.listeners > 0 || !self.signal.terminates_by_default() {
+            return;
+        }
+
+        let mut default = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        default.sa_sigaction = libc::SIG_DFL;
+        let mut replaced = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        if unsafe { libc::sigaction(self.signal.number(), &default, &mut replaced) } == 0 {
+            hook.replaced = Some(replaced);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct SignalStream(tokio::signal::windows::CtrlC);
+
+#[cfg(not(unix))]
+impl SignalStream {
+    fn new(signal: ProcessSignal) -> io::Result<Self> {
+        if signal.to_string() == "SIGINT" {
+            tokio::signal::windows::ctrl_c().map(Self)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Listening to {signal} is not supported on this platform"),
+            ))
+        }
+    }
+
+    async fn recv(&mut self) -> Option<()> {
+        self.0.recv().await
+    }
+}
+
+/**
+    Connects a Lua function as a handler for the given signal.
+
+    The handler will be called in a new Lua thread every time the signal is
+    received, until the returned handle is used to disconnect it. Note that
+    connected handlers keep the scheduler alive, just like a running server.
+*/
+pub(super) fn connect_signal_handler<'lua>(
+    lua: &'lua Lua,
+    signal: ProcessSignal,
+    callback: LuaFunction<'lua>,
+) -> LuaResult<LuaTable<'lua>> {
+    if lua.app_data_ref::<SignalHandlers>().is_none() {
+        lua.set_app_data(SignalHandlers::default());
+    }
+
+    let is_listening = lua
+        .app_data_ref::<SignalHandlers>()
+        .expect("Missing signal handlers")
+        .signals
+        .contains_key(&signal.number());
+    if !is_listening {
+        let stream = SignalStream::new(signal).into_lua_err()?;
+        let stop = Arc::new(Notify::new());
+        spawn_signal_listener(lua, signal, stream, Arc::clone(&stop));
+        lua.app_data_mut::<SignalHandlers>()
+            .expect("Missing signal handlers")
+            .signals
+            .insert(
+                signal.number(),
+                SignalListener {
+                    handlers: Vec::new(),
+                    stop,
+                },
+            );
+    }
+
+    let callback_key = lua.create_registry_value(callback)?;
+    let id = {
+        let mut handlers = lua
+            .app_data_mut::<SignalHandlers>()
+            .expect("Missing signal handlers");
+        let id = handlers.next_id;
+        handlers.next_id += 1;
+        handlers
+            .signals
+            .get_mut(&signal.number())
+            .expect("Missing signal listener")
+            .handlers
+            .push((id, callback_key));
+        id
+    };
+
+    TableBuilder::new(lua)?
+        .with_function("disconnect", move |lua, _: ()| {
+            disconnect_signal_handler(lua, signal, id);
+            Ok(())
+        })?
+        .build_readonly()
+}
+
+/**
+    Disconnects the handler with the given id, stopping the listener
+    for the signal if it was the last handler connected to it.
+
+    Disconnecting a handler that has already been disconnected does nothing.
+*/
+fn disconnect_signal_handler(lua: &Lua, signal: ProcessSignal, id: usize) {
+    let removed = {
+        let Some(mut handlers) = lua.app_data_mut::<SignalHandlers>() else {
+            return;
+        };
+        let Some(listener) = handlers.signals.get_mut(&signal.number()) else {
+            return;
+        };
+        let Some(index) = listener.handlers.iter().position(|(i, _)| *i == id) else {
+            return;
+        };
+        let (_, key) = listener.handlers.remove(index);
+        if listener.handlers.is_empty() {
+            let listener = handlers.signals.remove(&signal.number()).unwrap();
+            listener.stop.notify_one();
+        }
+        key
+    };
+    lua.remove_registry_value(removed).ok();
+}
+
+/**
+    Spawns the task that listens for the given signal in the current runtime,
+    calling all of the connected handlers every time the signal is received.
+
+    If the signal is received after all handlers have been disconnected,
+    but before the listener has stopped, the default action is taken, by
+    setting the exit code of the runtime, the same way `process.exit` does.
+*/
+fn spawn_signal_listener(
+    lua: &Lua,
+    signal: ProcessSignal,
+    mut stream: SignalStream,
+    stop: Arc<Notify>,
+) {
+    let lua_inner = lua
+        .app_data_ref::<Weak<Lua>>()
+        .expect("Missing weak lua ref")
+        .upgrade()
+        .expect("Lua was dropped unexpectedly");
+
+    ThreadTracker::spawn_source(lua, async move {
+        loop {
+            tokio::select! {
+                res = stream.recv() => {
+                    if res.is_none() {
+                        break;
+                    }
+                    if !call_signal_handlers(&lua_inner, signal) {
+                        if signal.terminates_by_default() {
+                            take_default_action(&lua_inner, signal);
+                        }
+                        break;
+                    }
+                }
+                () = stop.notified() => break,
+            }
+        }
+    });
+}
+
+fn call_signal_handlers(lua: &Rc<Lua>, signal: ProcessSignal) -> bool {
+    let callbacks = {
+        let Some(handlers) = lua.app_data_ref::<SignalHandlers>() else {
+            return false;
+        };
+        let Some(listener) = handlers.signals.get(&signal.number()) else {
+            return false;
+        };
+        listener
+            .handlers
+            .iter()
+            .map(|(_, key)| {
+                lua.registry_value::<LuaFunction>(key)
+                    .expect("Missing signal handler callback")
+            })
+            .collect::<Vec<_>>()
+    };
+    if callbacks.is_empty() {
+        return false;
+    }
+    for callback in callbacks {
+        lua.push_thread_back(callback, signal.to_string())
+            .expect("Failed to spawn signal handler thread");
+    }
+    true
+}
+
+fn take_default_action(lua: &Lua, signal: ProcessSignal) {
+    // NOTE: Shells report processes terminated by a signal using 128 + the signal number
+    let code = u8::try_from(128 + signal.number()).unwrap_or(u8::MAX);
+    lua.set_exit_code(ExitCode::from(code));
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use bstr::BString;
+use mlua::prelude::*;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::Mutex as AsyncMutex,
+};
+
+const DEFAULT_READ_SIZE: usize = 1024;
+
+type BoxedReader = Box<dyn AsyncRead + Send + Unpin>;
+type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/**
+    A readable stream of output from a child process, such as stdout or stderr.
+*/
+#[derive(Clone)]
+pub struct ChildProcessReader {
+    inner: Arc<AsyncMutex<BufReader<BoxedReader>>>,
+}
+
+impl ChildProcessReader {
+    pub fn new(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        let boxed: BoxedReader = Box::new(reader);
+        Self {
+            inner: Arc::new(AsyncMutex::new(BufReader::new(boxed))),
+        }
+    }
+
+    pub async fn read(&self, len: Option<usize>) -> LuaResult<Option<Vec<u8>>> {
+        let mut reader = self.inner.lock().await;
+        let mut buf = vec![0; len.unwrap_or(DEFAULT_READ_SIZE)];
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            Ok(None)
+        } else {
+            buf.truncate(read);
+            Ok(Some(buf))
+        }
+    }
+
+    pub async fn read_line(&self) -> LuaResult<Option<Vec<u8>>> {
+        let mut reader = self.inner.lock().await;
+        let mut buf = Vec::new();
+        let read = reader.read_until(b'\n', &mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if buf.ends_with(b"\n") {
+            buf.pop();
+            if buf.ends_with(b"\r") {
+                buf.pop();
+            }
+        }
+        Ok(Some(buf))
+    }
+
+    pub async fn read_to_end(&self) -> LuaResult<Vec<u8>> {
+        let mut reader = self.inner.lock().await;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+}
+
+impl LuaUserData for ChildProcessReader {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("read", |lua, this, len: Option<usize>| async move {
+            match this.read(len).await? {
+                Some(bytes) => Ok(LuaValue::String(lua.create_string(bytes)?)),
+                None => Ok(LuaValue::Nil),
+            }
+        });
+
+        methods.add_async_method("readLine", |lua, this, _: ()| async move {
+            match this.read_line().await? {
+                Some(bytes) => Ok(LuaValue::String(lua.create_string(bytes)?)),
+                None => Ok(LuaValue::Nil),
+            }
+        });
+
+        methods.add_async_method("readToEnd", |lua, this, _: ()| async move {
+            let bytes = this.read_to_end().await?;
+            lua.create_string(bytes)
+        });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "ChildProcessReader");
+    }
+}
+
+/**
+    A writable stream of input to a child process, such as stdin.
+
+    Closing the writer will drop the underlying stream, signaling
+    to the child process that no more input will be given.
+*/
+#[derive(Clone)]
+pub struct ChildProcessWriter {
+    inner: Arc<AsyncMutex<Option<BoxedWriter>>>,
+}
+
+impl ChildProcessWriter {
+    pub fn new(writer: impl AsyncWrite + Send + Unpin + 'static) -> Self {
+        let boxed: BoxedWriter = Box::new(writer);
+        Self {
+            inner: Arc::new(AsyncMutex::new(Some(boxed))),
+        }
+    }
+
+    pub async fn write(&self, bytes: &[u8]) -> LuaResult<()> {
+        let mut guard = self.inner.lock().await;
+        let writer = guard
+            .as_mut()
+            .ok_or_else(|| LuaError::runtime("Stream has already been closed"))?;
+        writer.write_all(bytes).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    pub async fn close(&self) -> LuaResult<()> {
+        let mut guard = self.inner.lock().await;
+        if let Some(mut writer) = guard.take() {
+            writer.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+impl LuaUserData for ChildProcessWriter {
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("write", |_, this, data: BString| async move {
+            this.write(data.as_ref()).await
+        });
+
+        methods.add_async_method("close", |_, this, _: ()| async move { this.close().await });
+    }
+
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "ChildProcessWriter");
+    }
+}
@@ -1,19 +1,90 @@
-use std::process::ExitStatus;
+use std::{
+    process::ExitStatus,
+    time::{Duration, Instant},
+};
 
 use mlua::prelude::*;
 use tokio::{
     io::{self, AsyncRead, AsyncReadExt},
     process::Child,
-    task,
+    task, time,
 };
 
-use super::{options::ProcessSpawnOptionsStdioKind, tee_writer::AsyncTeeWriter};
+use crate::lune::util::TableBuilder;
+
+use super::{
+    options::ProcessSpawnOptionsStdioKind,
+    rusage::{ChildExitWaiter, ResourceUsage},
+    signal::{exit_code_and_signal, ProcessSignal},
+    tee_writer::AsyncTeeWriter,
+};
 
 #[derive(Debug, Clone)]
 pub(super) struct WaitForChildResult {
+    pub pid: u32,
     pub status: ExitStatus,
     pub stdout: Vec<u8>,
     pub stderr: Vec<u8>,
+    pub timed_out: bool,
+    pub duration: Duration,
+    pub usage: Option<ResourceUsage>,
+}
+
+impl WaitForChildResult {
+    /**
+        Creates a result for a detached child process, which is never waited
+        on, and will always be considered successful once it has been spawned.
+    */
+    pub fn detached(pid: u32) -> Self {
+        Self {
+            pid,
+            status: ExitStatus::default(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            timed_out: false,
+            duration: Duration::ZERO,
+            usage: None,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        exit_code_and_signal(self.status).0 == 0 && !self.timed_out
+    }
+
+    pub fn into_lua_table(self, lua: &Lua) -> LuaResult<LuaTable<'_>> {
+        let (code, signal) = exit_code_and_signal(self.status);
+
+        // Resource usage is only available on some platforms
+        let usage = match self.usage {
+            None => LuaValue::Nil,
+            Some(usage) => LuaValue::Table(
+                TableBuilder::new(lua)?
+                    .with_value("userTime", usage.user_time.as_secs_f64())?
+                    .with_value("systemTime", usage.system_time.as_secs_f64())?
+                    .with_value("maxRss", usage.max_rss)?
+                    .build_readonly()?,
+            ),
+        };
+
+        // Construct and return a readonly lua table with results
+        TableBuilder::new(lua)?
+            .with_value("ok", code == 0 && !self.timed_out)?
+            .with_value("code", code)?
+            .with_value("pid", self.pid)?
+            .with_value("stdout", lua.create_string(&self.stdout)?)?
+            .with_value("stderr", lua.create_string(&self.stderr)?)?
+            .with_value("timedOut", self.timed_out)?
+            .with_value(
+                "signal",
+                signal
+                    .and_then(ProcessSignal::from_number)
+                    .map(|s| s.to_string()),
+            )?
+            .with_value("signalNumber", signal)?
+            .with_value("duration", self.duration.as_secs_f64())?
+            .with_value("usage", usage)?
+            .build_readonly()
+    }
 }
 
 async fn read_with_stdio_kind<R>(
@@ -50,25 +121,72 @@ where
     })
 }
 
+#[cfg(unix)]
+fn kill_child(child: &mut Child, kill_process_group: bool) -> io::Result<()> {
+    match child.id() {
+        Some(pid) if kill_process_group => {
+            #[allow(clippy::cast_possible_wrap)]
+            let res = unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+            if res == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+        _ => child.start_kill(),
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_child(child: &mut Child, _: bool) -> io::Result<()> {
+    child.start_kill()
+}
+
 pub(super) async fn wait_for_child(
     mut child: Child,
     stdout_kind: ProcessSpawnOptionsStdioKind,
     stderr_kind: ProcessSpawnOptionsStdioKind,
+    timeout: Option<Duration>,
+    kill_process_group: bool,
 ) -> LuaResult<WaitForChildResult> {
+    let pid = child.id().expect("Child process was already reaped");
     let stdout_opt = child.stdout.take();
     let stderr_opt = child.stderr.take();
 
     let stdout_task = task::spawn(read_with_stdio_kind(stdout_opt, stdout_kind));
     let stderr_task = task::spawn(read_with_stdio_kind(stderr_opt, stderr_kind));
 
-    let status = child.wait().await.expect("Child process failed to start");
+    let start = Instant::now();
+    let mut waiter = ChildExitWaiter::new(&child);
+
+    let mut timed_out = false;
+    let (status, usage) = match timeout {
+        None => waiter.wait(&mut child).await,
+        Some(timeout) => match time::timeout(timeout, waiter.wait(&mut child)).await {
+            Ok(res) => res,
+            Err(_) => {
+                timed_out = true;
+                // NOTE: The child may have exited right as we timed out, in which
+                // case killing it fails, but we can still wait for its status
+                kill_child(&mut child, kill_process_group).ok();
+                waiter.wait(&mut child).await
+            }
+        },
+    }
+    .expect("Child process failed to start");
+
+    let duration = start.elapsed();
 
     let stdout_buffer = stdout_task.await.into_lua_err()??;
     let stderr_buffer = stderr_task.await.into_lua_err()??;
 
     Ok(WaitForChildResult {
+        pid,
         status,
         stdout: stdout_buffer,
         stderr: stderr_buffer,
+        timed_out,
+        duration,
+        usage,
     })
 }
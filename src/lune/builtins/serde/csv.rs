@@ -0,0 +1,162 @@
+use indexmap::IndexSet;
+use mlua::prelude::*;
+
+use super::ordered::OrderedValue;
+
+/**
+    How the header row of a csv file is written when encoding.
+*/
+#[derive(Debug, Default, Clone)]
+pub enum CsvHeader {
+    /// Writes a header row using the keys of all records, in the order they first appear.
+    #[default]
+    Keys,
+    /// Does not write a header row, records are still written using their keys, in order.
+    None,
+    /// Writes a header row using the given columns, and only those columns.
+    Columns(Vec<String>),
+}
+
+impl<'lua> FromLua<'lua> for CsvHeader {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil | LuaValue::Boolean(true) => Ok(Self::Keys),
+            LuaValue::Boolean(false) => Ok(Self::None),
+            LuaValue::Table(_) => Ok(Self::Columns(Vec::from_lua(value, lua)?)),
+            value => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "CsvHeader",
+                message: Some(String::from("Expected a boolean or an array of columns")),
+            }),
+        }
+    }
+}
+
+/**
+    Converts a csv delimiter given as a string into a single byte.
+*/
+pub fn csv_delimiter(delimiter: Option<String>) -> LuaResult<u8> {
+    match delimiter.as_deref().map(str::as_bytes) {
+        None => Ok(b','),
+        Some([byte]) => Ok(*byte),
+        Some(_) => Err(LuaError::runtime(
+            "CSV delimiter must be a single ascii character",
+        )),
+    }
+}
+
+/**
+    Decodes a csv file into an array of rows.
+
+    If the file has a header row, each row is a map from the columns in the header row to
+    the values in that row, otherwise each row is an array of values. All values are strings.
+*/
+pub fn decode_csv(bytes: &[u8], header: bool, delimiter: u8) -> LuaResult<OrderedValue> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .has_headers(header)
+        .delimiter(delimiter)
+        .from_reader(bytes);
+
+    let columns = if header {
+        let columns = reader.byte_headers().into_lua_err()?;
+        columns
+            .iter()
+            .map(|column| OrderedValue::String(column.to_vec()))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut rows = Vec::new();
+    for record in reader.byte_records() {
+        let values = record
+            .into_lua_err()?
+            .iter()
+            .map(|value| OrderedValue::String(value.to_vec()))
+            .collect::<Vec<_>>();
+        rows.push(if header {
+            OrderedValue::Map(columns.iter().cloned().zip(values).collect())
+        } else {
+            OrderedValue::Array(values)
+        });
+    }
+
+    Ok(OrderedValue::Array(rows))
+}
+
+/**
+    Encodes an array of rows into a csv file.
+
+    Rows may either all be arrays of values, which are written as they are,
+    or all be maps from columns to values, which are written as records.
+*/
+pub fn encode_csv(value: &OrderedValue, header: &CsvHeader, delimiter: u8) -> LuaResult<Vec<u8>> {
+    let rows = match value {
+        OrderedValue::Array(rows) => rows.as_slice(),
+        OrderedValue::Map(entries) if entries.is_empty() => &[],
+        _ => return Err(LuaError::runtime("CSV must be an array of rows")),
+    };
+
+    let mut writer = ::csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+
+    let is_records = rows.iter().any(|row| matches!(row, OrderedValue::Map(_)));
+    if is_records {
+        let columns = match header {
+            CsvHeader::Columns(columns) => columns.clone(),
+            CsvHeader::Keys | CsvHeader::None => record_columns(rows)?,
+        };
+        if !matches!(header, CsvHeader::None) {
+            writer.write_record(&columns).into_lua_err()?;
+        }
+        for row in rows {
+            if !matches!(row, OrderedValue::Map(_)) {
+                return Err(LuaError::runtime(
+                    "CSV rows must either all be records or all be arrays",
+                ));
+            }
+            let values = columns
+                .iter()
+                .map(|column| row.get(column).map_or(Ok(String::new()), value_to_text))
+                .collect::<LuaResult<Vec<_>>>()?;
+            writer.write_record(values).into_lua_err()?;
+        }
+    } else {
+        if let CsvHeader::Columns(columns) = header {
+            writer.write_record(columns).into_lua_err()?;
+        }
+        for row in rows {
+            let OrderedValue::Array(values) = row else {
+                return Err(LuaError::runtime(
+                    "CSV rows must either all be records or all be arrays",
+                ));
+            };
+            let values = values
+                .iter()
+                .map(value_to_text)
+                .collect::<LuaResult<Vec<_>>>()?;
+            writer.write_record(values).into_lua_err()?;
+        }
+    }
+
+    writer.into_inner().into_lua_err()
+}
+
+fn record_columns(rows: &[OrderedValue]) -> LuaResult<Vec<String>> {
+    let mut columns = IndexSet::new();
+    for row in rows {
+        if let OrderedValue::Map(entries) = row {
+            for (key, _) in entries {
+                columns.insert(value_to_text(key)?);
+            }
+        }
+    }
+    Ok(columns.into_iter().collect())
+}
+
+fn value_to_text(value: &OrderedValue) -> LuaResult<String> {
+    value
+        .to_text()
+        .ok_or_else(|| LuaError::runtime("CSV values can not be tables"))
+}
@@ -0,0 +1,573 @@
+use std::{cell::RefCell, fmt::Write as _};
+
+use bstr::{BString, ByteSlice};
+use mlua::prelude::*;
+
+use serde_json::Value as JsonValue;
+
+use super::encode_decode::{json_to_lua_value, EncodeDecodeFormat};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/**
+    Checks that the given format can be decoded incrementally,
+    which is currently only supported for the json format.
+*/
+pub fn ensure_streamable(format: EncodeDecodeFormat, function_name: &str) -> LuaResult<()> {
+    match format {
+        EncodeDecodeFormat::Json => Ok(()),
+        _ => Err(LuaError::runtime(format!(
+            "serde.{function_name} only supports the json format"
+        ))),
+    }
+}
+
+/**
+    Takes the state of a stream iterator out of its cell, to be put back once it has advanced.
+
+    The state is never borrowed while decoding, since reader functions may yield or call the
+    iterator again - such a nested call gets an error instead of the state that is in use.
+*/
+pub fn take_stream_state<T>(state: &RefCell<Option<T>>, function_name: &str) -> LuaResult<T> {
+    state.borrow_mut().take().ok_or_else(|| {
+        LuaError::runtime(format!(
+            "The iterator returned from serde.{function_name} is already running"
+        ))
+    })
+}
+
+/**
+    A source of bytes to decode incrementally.
+
+    Strings are read in chunks directly from lua, without copying the full string,
+    and functions are called whenever more bytes are needed, and may yield.
+*/
+#[derive(Debug)]
+pub enum ChunkSource {
+    String { key: LuaRegistryKey, offset: usize },
+    Bytes { bytes: Vec<u8>, offset: usize },
+    Reader(LuaRegistryKey),
+}
+
+impl ChunkSource {
+    /**
+        Reads the next chunk of bytes, returning `None` once the source has ended.
+
+        Chunks returned from reader functions may be empty, which does not end the source.
+    */
+    async fn read_chunk(&mut self, lua: &Lua) -> LuaResult<Option<Vec<u8>>> {
+        match self {
+            Self::String { key, offset } => {
+                let string = lua.registry_value::<LuaString>(key)?;
+                let bytes = string.as_bytes();
+                Ok(next_chunk(bytes, offset))
+            }
+            Self::Bytes { bytes, offset } => Ok(next_chunk(bytes, offset)),
+            Self::Reader(key) => {
+                let reader = lua.registry_value::<LuaFunction>(key)?;
+                let chunk = reader.call_async::<_, Option<BString>>(()).await?;
+                Ok(chunk.map(Vec::from))
+            }
+        }
+    }
+}
+
+fn next_chunk(bytes: &[u8], offset: &mut usize) -> Option<Vec<u8>> {
+    if *offset >= bytes.len() {
+        return None;
+    }
+    let end = bytes.len().min(*offset + CHUNK_SIZE);
+    let chunk = bytes[*offset..end].to_vec();
+    *offset = end;
+    Some(chunk)
+}
+
+impl<'lua> FromLua<'lua> for ChunkSource {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::String(s) => Ok(Self::String {
+                key: lua.create_registry_value(s)?,
+                offset: 0,
+            }),
+            LuaValue::Function(f) => Ok(Self::Reader(lua.create_registry_value(f)?)),
+            value => match BString::from_lua(value.clone(), lua) {
+                Ok(bytes) => Ok(Self::Bytes {
+                    bytes: bytes.into(),
+                    offset: 0,
+                }),
+                Err(_) => Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "ChunkSource",
+                    message: Some(String::from(
+                        "Expected a string, buffer, or function returning chunks of a string",
+                    )),
+                }),
+            },
+        }
+    }
+}
+
+/**
+    A buffer of bytes that have been read from a [`ChunkSource`] but not yet consumed.
+
+    Bytes before the current position are discarded whenever more bytes are read,
+    unless they are part of a value that is currently being captured.
+*/
+#[derive(Debug)]
+struct StreamBuffer {
+    source: ChunkSource,
+    bytes: Vec<u8>,
+    pos: usize,
+    capture: Option<usize>,
+    ended: bool,
+}
+
+impl StreamBuffer {
+    fn new(source: ChunkSource) -> Self {
+        Self {
+            source,
+            bytes: Vec::new(),
+            pos: 0,
+            capture: None,
+            ended: false,
+        }
+    }
+
+    async fn fill(&mut self, lua: &Lua) -> LuaResult<bool> {
+        if self.ended {
+            return Ok(false);
+        }
+        let keep = self.capture.unwrap_or(self.pos);
+        self.bytes.drain(..keep);
+        self.pos -= keep;
+        if let Some(capture) = self.capture.as_mut() {
+            *capture -= keep;
+        }
+        match self.source.read_chunk(lua).await? {
+            Some(chunk) => {
+                self.bytes.extend_from_slice(&chunk);
+                Ok(true)
+            }
+            None => {
+                self.ended = true;
+                Ok(false)
+            }
+        }
+    }
+
+    async fn peek(&mut self, lua: &Lua) -> LuaResult<Option<u8>> {
+        while self.pos >= self.bytes.len() {
+            if !self.fill(lua).await? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.bytes[self.pos]))
+    }
+
+    async fn peek_required(&mut self, lua: &Lua) -> LuaResult<u8> {
+        self.peek(lua)
+            .await?
+            .ok_or_else(|| LuaError::runtime("Unexpected end of JSON input"))
+    }
+
+    async fn next_required(&mut self, lua: &Lua) -> LuaResult<u8> {
+        let byte = self.peek_required(lua).await?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    async fn skip_whitespace(&mut self, lua: &Lua) -> LuaResult<Option<u8>> {
+        while let Some(byte) = self.peek(lua).await? {
+            if !byte.is_ascii_whitespace() {
+                return Ok(Some(byte));
+            }
+            self.pos += 1;
+        }
+        Ok(None)
+    }
+
+    async fn next_line(&mut self, lua: &Lua) -> LuaResult<Option<Vec<u8>>> {
+        let mut searched = self.pos;
+        loop {
+            if let Some(index) = self.bytes[searched..].find_byte(b'\n') {
+                let end = searched + index;
+                let line = self.bytes[self.pos..end].to_vec();
+                self.pos = end + 1;
+                return Ok(Some(line));
+            }
+            searched = self.bytes.len() - self.pos;
+            if !self.fill(lua).await? {
+                break;
+            }
+            searched += self.pos;
+        }
+        if self.pos < self.bytes.len() {
+            let line = self.bytes[self.pos..].to_vec();
+            self.pos = self.bytes.len();
+            Ok(Some(line))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/**
+    Decodes newline-delimited json, one value per line.
+
+    Empty lines are skipped, but are still counted for line numbers.
+*/
+#[derive(Debug)]
+pub struct JsonLines {
+    buffer: StreamBuffer,
+    line: usize,
+}
+
+impl JsonLines {
+    pub fn new(source: ChunkSource) -> Self {
+        Self {
+            buffer: StreamBuffer::new(source),
+            line: 0,
+        }
+    }
+
+    /**
+        Decodes the next value, returning its line number along with the value,
+        or nothing once there are no more lines - this may be used as an iterator.
+    */
+    pub async fn next<'lua>(&mut self, lua: &'lua Lua) -> LuaResult<LuaMultiValue<'lua>> {
+        while let Some(line) = self.buffer.next_line(lua).await? {
+            self.line += 1;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let value: JsonValue = serde_json::from_slice(&line).map_err(|e| {
+                LuaError::runtime(format!("Invalid JSON on line {}: {e}", self.line))
+            })?;
+            return (self.line, json_to_lua_value(lua, &value)?).into_lua_multi(lua);
+        }
+        Ok(LuaMultiValue::new())
+    }
+}
+
+/**
+    A single segment of a path to select values in a json document.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+    Any,
+}
+
+impl PathSegment {
+    fn matches(&self, element: &PathElement) -> bool {
+        match (self, element) {
+            (Self::Any, _) => true,
+            (Self::Key(key), PathElement::Key(other)) => key == other,
+            (Self::Index(index), PathElement::Index(other)) => index == other,
+            _ => false,
+        }
+    }
+}
+
+/**
+    Parses a path such as `items[*].name`, where keys are separated by dots,
+    indices are given in brackets, and `*` matches any key or index.
+*/
+fn parse_path(path: &str) -> LuaResult<Vec<PathSegment>> {
+    let invalid = |message: &str| LuaError::runtime(format!("Invalid path '{path}' - {message}"));
+
+    let rest = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = Vec::new();
+    let mut chars = rest.chars().peekable();
+    let mut first = true;
+    while let Some(&c) = chars.peek() {
+        if c == '[' {
+            chars.next();
+            let inner = chars.by_ref().take_while(|c| *c != ']').collect::<String>();
+            segments.push(match inner.trim() {
+                "*" => PathSegment::Any,
+                index => PathSegment::Index(
+                    index
+                        .parse()
+                        .map_err(|_| invalid("indices must be whole numbers or '*'"))?,
+                ),
+            });
+        } else {
+            if c == '.' {
+                chars.next();
+            } else if !first {
+                return Err(invalid("keys must be separated by '.'"));
+            }
+            let mut key = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '.' || c == '[' {
+                    break;
+                }
+                key.push(c);
+                chars.next();
+            }
+            segments.push(match key.as_str() {
+                "" => return Err(invalid("keys must not be empty")),
+                "*" => PathSegment::Any,
+                _ => PathSegment::Key(key),
+            });
+        }
+        first = false;
+    }
+    Ok(segments)
+}
+
+/**
+    The key or index of the value currently being decoded in an object or array.
+*/
+#[derive(Debug, Clone)]
+enum PathElement {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug)]
+struct Frame {
+    is_array: bool,
+    started: bool,
+    next_index: usize,
+    element: Option<PathElement>,
+}
+
+/**
+    Decodes values at a given path in a json document, one at a time,
+    without decoding the rest of the document.
+
+    Only objects and arrays along the path are entered, everything
+    else is skipped over as quickly as possible without being decoded.
+*/
+#[derive(Debug)]
+pub struct JsonSelector {
+    buffer: StreamBuffer,
+    path: Vec<PathSegment>,
+    stack: Vec<Frame>,
+    started: bool,
+    finished: bool,
+}
+
+impl JsonSelector {
+    pub fn new(source: ChunkSource, path: &str) -> LuaResult<Self> {
+        Ok(Self {
+            buffer: StreamBuffer::new(source),
+            path: parse_path(path)?,
+            stack: Vec::new(),
+            started: false,
+            finished: false,
+        })
+    }
+
+    /**
+        Decodes the next selected value, returning its full path along with the value,
+        or nothing once the document has ended - this may be used as an iterator.
+    */
+    pub async fn next<'lua>(&mut self, lua: &'lua Lua) -> LuaResult<LuaMultiValue<'lua>> {
+        let result = self.next_match(lua).await;
+        if result.is_err() {
+            self.finished = true;
+        }
+        match result? {
+            Some((path, value)) => (path, json_to_lua_value(lua, &value)?).into_lua_multi(lua),
+            None => Ok(LuaMultiValue::new()),
+        }
+    }
+
+    async fn next_match(&mut self, lua: &Lua) -> LuaResult<Option<(String, JsonValue)>> {
+        loop {
+            if self.finished {
+                return Ok(None);
+            }
+
+            let Some(frame) = self.stack.last() else {
+                if self.started {
+                    if self.buffer.skip_whitespace(lua).await?.is_some() {
+                        return Err(LuaError::runtime(
+                            "Unexpected trailing characters after JSON document",
+                        ));
+                    }
+                    self.finished = true;
+                    return Ok(None);
+                }
+                self.started = true;
+                if self.buffer.skip_whitespace(lua).await?.is_none() {
+                    return Err(LuaError::runtime("Unexpected end of JSON input"));
+                }
+                if let Some(found) = self.visit_value(lua).await? {
+                    return Ok(Some(found));
+                }
+                continue;
+            };
+
+            let (is_array, started) = (frame.is_array, frame.started);
+            let close = if is_array { b']' } else { b'}' };
+
+            self.buffer.skip_whitespace(lua).await?;
+            let byte = self.buffer.peek_required(lua).await?;
+            if byte == close {
+                self.buffer.pos += 1;
+                self.stack.pop();
+                continue;
+            }
+            if started {
+                if byte != b',' {
+                    return Err(unexpected_character(byte));
+                }
+                self.buffer.pos += 1;
+                self.buffer.skip_whitespace(lua).await?;
+            }
+
+            let element = if is_array {
+                let frame = self.stack.last_mut().expect("Frame exists");
+                frame.next_index += 1;
+                PathElement::Index(frame.next_index - 1)
+            } else {
+                let key = self.read_key(lua).await?;
+                self.buffer.skip_whitespace(lua).await?;
+                match self.buffer.next_required(lua).await? {
+                    b':' => {}
+                    byte => return Err(unexpected_character(byte)),
+                }
+                self.buffer.skip_whitespace(lua).await?;
+                PathElement::Key(key)
+            };
+
+            let frame = self.stack.last_mut().expect("Frame exists");
+            frame.started = true;
+            frame.element = Some(element);
+
+            if let Some(found) = self.visit_value(lua).await? {
+                return Ok(Some(found));
+            }
+        }
+    }
+
+    async fn visit_value(&mut self, lua: &Lua) -> LuaResult<Option<(String, JsonValue)>> {
+        let depth = self.stack.len();
+        let matched = depth == 0
+            || self.stack[depth - 1]
+                .element
+                .as_ref()
+                .is_some_and(|element| self.path[depth - 1].matches(element));
+
+        if !matched {
+            self.scan_value(lua).await?;
+            return Ok(None);
+        }
+
+        if depth == self.path.len() {
+            self.buffer.capture = Some(self.buffer.pos);
+            let scanned = self.scan_value(lua).await;
+            let start = self.buffer.capture.take().expect("Capture was set");
+            scanned?;
+            let path = self.current_path();
+            let value = serde_json::from_slice(&self.buffer.bytes[start..self.buffer.pos])
+                .map_err(|e| LuaError::runtime(format!("Invalid JSON at '{path}': {e}")))?;
+            return Ok(Some((path, value)));
+        }
+
+        match self.buffer.peek_required(lua).await? {
+            byte @ (b'{' | b'[') => {
+                self.buffer.pos += 1;
+                self.stack.push(Frame {
+                    is_array: byte == b'[',
+                    started: false,
+                    next_index: 0,
+                    element: None,
+                });
+            }
+            _ => self.scan_value(lua).await?,
+        }
+        Ok(None)
+    }
+
+    async fn read_key(&mut self, lua: &Lua) -> LuaResult<String> {
+        match self.buffer.peek_required(lua).await? {
+            b'"' => {}
+            byte => return Err(unexpected_character(byte)),
+        }
+        self.buffer.capture = Some(self.buffer.pos);
+        let scanned = self.scan_string(lua).await;
+        let start = self.buffer.capture.take().expect("Capture was set");
+        scanned?;
+        serde_json::from_slice(&self.buffer.bytes[start..self.buffer.pos]).into_lua_err()
+    }
+
+    async fn scan_string(&mut self, lua: &Lua) -> LuaResult<()> {
+        self.buffer.pos += 1;
+        loop {
+            match self.buffer.next_required(lua).await? {
+                b'\\' => {
+                    self.buffer.next_required(lua).await?;
+                }
+                b'"' => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    async fn scan_value(&mut self, lua: &Lua) -> LuaResult<()> {
+        match self.buffer.peek_required(lua).await? {
+            b'"' => self.scan_string(lua).await,
+            b'{' | b'[' => {
+                let mut depth = 0usize;
+                loop {
+                    match self.buffer.peek_required(lua).await? {
+                        b'"' => self.scan_string(lua).await?,
+                        b'{' | b'[' => {
+                            depth += 1;
+                            self.buffer.pos += 1;
+                        }
+                        b'}' | b']' => {
+                            depth -= 1;
+                            self.buffer.pos += 1;
+                            if depth == 0 {
+                                return Ok(());
+                            }
+                        }
+                        _ => self.buffer.pos += 1,
+                    }
+                }
+            }
+            byte => {
+                let mut length = 0;
+                while let Some(byte) = self.buffer.peek(lua).await? {
+                    if byte.is_ascii_whitespace() || matches!(byte, b',' | b':' | b']' | b'}') {
+                        break;
+                    }
+                    self.buffer.pos += 1;
+                    length += 1;
+                }
+                if length == 0 {
+                    Err(unexpected_character(byte))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn current_path(&self) -> String {
+        let mut path = String::from("$");
+        for frame in &self.stack {
+            match &frame.element {
+                Some(PathElement::Key(key)) => write!(path, ".{key}"),
+                Some(PathElement::Index(index)) => write!(path, "[{index}]"),
+                None => Ok(()),
+            }
+            .expect("Writing to a string never fails");
+        }
+        path
+    }
+}
+
+fn unexpected_character(byte: u8) -> LuaError {
+    LuaError::runtime(format!(
+        "Unexpected character '{}' in JSON input",
+        char::from(byte).escape_default()
+    ))
+}
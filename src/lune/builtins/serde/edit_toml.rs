@@ -0,0 +1,341 @@
+use std::cell::RefCell;
+
+use bstr::{BString, ByteSlice};
+use mlua::prelude::*;
+use serde::Serialize;
+use toml::Value as TomlValue;
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
+
+use super::encode_decode::{lua_value_to, LUA_SERIALIZE_OPTIONS};
+
+/**
+    A segment of a path into a toml document, either a key or a (zero-based) index.
+*/
+#[derive(Debug, Clone)]
+enum TomlPathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/**
+    A path into a toml document.
+
+    Paths may be given either as a string of keys separated by dots, such as `package.version`,
+    or as an array of keys and indices, such as `{ "dependencies", "serde.json" }` or `{ "bin", 1 }`,
+    where indices start at one, the same as for lua arrays.
+*/
+#[derive(Debug, Clone)]
+struct TomlPath(Vec<TomlPathSegment>);
+
+impl<'lua> FromLua<'lua> for TomlPath {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let segments = match &value {
+            LuaValue::Nil => Vec::new(),
+            LuaValue::String(s) => s
+                .to_str()?
+                .split('.')
+                .map(|key| TomlPathSegment::Key(key.to_string()))
+                .collect(),
+            LuaValue::Table(t) => t
+                .clone()
+                .sequence_values::<LuaValue>()
+                .map(|segment| match segment? {
+                    LuaValue::String(s) => Ok(TomlPathSegment::Key(s.to_str()?.to_string())),
+                    LuaValue::Integer(i) if i >= 1 => Ok(TomlPathSegment::Index(
+                        usize::try_from(i - 1).into_lua_err()?,
+                    )),
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    LuaValue::Number(n) if n >= 1.0 && n.fract() == 0.0 => {
+                        Ok(TomlPathSegment::Index(n as usize - 1))
+                    }
+                    _ => Err(LuaError::runtime(
+                        "Path segments must be strings or positive whole numbers",
+                    )),
+                })
+                .collect::<LuaResult<_>>()?,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "TomlPath",
+                    message: Some(String::from("Expected a string or an array of keys")),
+                })
+            }
+        };
+        Ok(Self(segments))
+    }
+}
+
+fn get_item<'a>(item: &'a Item, segment: &TomlPathSegment) -> Option<&'a Item> {
+    match segment {
+        TomlPathSegment::Key(key) => item.get(key.as_str()),
+        TomlPathSegment::Index(index) => item.get(*index),
+    }
+}
+
+fn get_item_mut<'a>(item: &'a mut Item, segment: &TomlPathSegment) -> Option<&'a mut Item> {
+    match segment {
+        // NOTE: Indexing with a key would insert it if missing, so check that it exists first
+        TomlPathSegment::Key(key) if item.get(key.as_str()).is_some() => item.get_mut(key.as_str()),
+        TomlPathSegment::Index(index) => item.get_mut(*index),
+        TomlPathSegment::Key(_) => None,
+    }
+}
+
+/**
+    Converts a toml item into a lua value, using the same conversion as `serde.decode`.
+*/
+fn item_to_lua<'lua>(lua: &'lua Lua, item: &Item) -> LuaResult<LuaValue<'lua>> {
+    if item.is_none() {
+        return Ok(LuaValue::Nil);
+    }
+    let mut document = DocumentMut::new();
+    document.insert("value", item.clone());
+    let mut value: TomlValue = toml_edit::de::from_document(document).into_lua_err()?;
+    let value = value
+        .as_table_mut()
+        .and_then(|table| table.remove("value"))
+        .ok_or_else(|| LuaError::runtime("Failed to convert toml item"))?;
+    lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+}
+
+/**
+    Converts a lua value into a toml item, using the same conversion as `serde.encode`.
+
+    Tables are converted into standard tables and arrays of tables, unless they are
+    being placed inside of an inline table or an array, where they must also be inline.
+*/
+fn lua_to_item(lua: &Lua, value: LuaValue, inline: bool) -> LuaResult<Item> {
+    let value: TomlValue = lua_value_to(lua, value)?;
+    let value = value
+        .serialize(toml_edit::ser::ValueSerializer::new())
+        .into_lua_err()?;
+    if inline {
+        return Ok(Item::Value(value));
+    }
+    Ok(match value {
+        Value::InlineTable(table) => Item::Table(table.into_table()),
+        Value::Array(array) if !array.is_empty() && array.iter().all(Value::is_inline_table) => {
+            let mut tables = ArrayOfTables::new();
+            for value in array {
+                if let Value::InlineTable(table) = value {
+                    tables.push(table.into_table());
+                }
+            }
+            Item::ArrayOfTables(tables)
+        }
+        value => Item::Value(value),
+    })
+}
+
+/**
+    Replaces an item, keeping any comments and whitespace around the previous value.
+*/
+fn replace_item(existing: &mut Item, mut item: Item) {
+    if let (Item::Value(previous), Item::Value(value)) = (&*existing, &mut item) {
+        *value.decor_mut() = previous.decor().clone();
+    }
+    *existing = item;
+}
+
+/**
+    A toml document that keeps all of its formatting, comments and order of keys when edited.
+*/
+#[derive(Debug)]
+pub struct TomlDocument {
+    document: RefCell<DocumentMut>,
+}
+
+impl TomlDocument {
+    pub fn parse(source: BString) -> LuaResult<Self> {
+        let source = source
+            .to_str()
+            .map_err(|_| LuaError::runtime("TOML must be valid utf-8"))?;
+        let document = source.parse::<DocumentMut>().into_lua_err()?;
+        Ok(Self {
+            document: RefCell::new(document),
+        })
+    }
+
+    fn get<'lua>(&self, lua: &'lua Lua, path: &TomlPath) -> LuaResult<LuaValue<'lua>> {
+        let document = self.document.borrow();
+        let mut item = document.as_item();
+        for segment in &path.0 {
+            match get_item(item, segment) {
+                Some(inner) => item = inner,
+                None => return Ok(LuaValue::Nil),
+            }
+        }
+        item_to_lua(lua, item)
+    }
+
+    fn set(&self, lua: &Lua, path: &TomlPath, value: LuaValue) -> LuaResult<()> {
+        if value.is_nil() {
+            return self.remove(lua, path).map(|_| ());
+        }
+        let Some((last, parents)) = path.0.split_last() else {
+            return Err(LuaError::runtime("Can not set the root of a document"));
+        };
+
+        let mut document = self.document.borrow_mut();
+        let mut item = document.as_item_mut();
+        for segment in parents {
+            if get_item(item, segment).is_none() {
+                let TomlPathSegment::Key(key) = segment else {
+                    return Err(LuaError::runtime(format!(
+                        "Index {} is out of bounds",
+                        path_index(segment)
+                    )));
+                };
+                match item {
+                    Item::Table(table) => {
+                        let mut inner = Table::new();
+                        inner.set_implicit(true);
+                        table.insert(key, Item::Table(inner));
+                    }
+                    Item::Value(Value::InlineTable(table)) => {
+                        table.insert(key, Value::InlineTable(toml_edit::InlineTable::new()));
+                    }
+                    _ => return Err(not_a_table(key)),
+                }
+            }
+            item = get_item_mut(item, segment).expect("Item was inserted");
+        }
+
+        match (item, last) {
+            (Item::Table(table), TomlPathSegment::Key(key)) => {
+                let new_item = lua_to_item(lua, value, false)?;
+                match table.get_mut(key) {
+                    Some(existing) => replace_item(existing, new_item),
+                    None => {
+                        table.insert(key, new_item);
+                    }
+                }
+            }
+            (Item::Value(Value::InlineTable(table)), TomlPathSegment::Key(key)) => {
+                let new_item = lua_to_item(lua, value, true)?;
+                match table.get_mut(key) {
+                    Some(existing) => {
+                        let mut existing_item = Item::Value(existing.clone());
+                        replace_item(&mut existing_item, new_item);
+                        *existing = existing_item.into_value().expect("Item is a value");
+                    }
+                    None => {
+                        let value = new_item.into_value().expect("Item is a value");
+                        table.insert(key, value);
+                    }
+                }
+            }
+            (Item::Value(Value::Array(array)), TomlPathSegment::Index(index)) => {
+                let new_item = lua_to_item(lua, value, true)?;
+                let mut new_value = new_item.into_value().expect("Item is a value");
+                let len = array.len();
+                match array.get_mut(*index) {
+                    Some(existing) => {
+                        *new_value.decor_mut() = existing.decor().clone();
+                        *existing = new_value;
+                    }
+                    None if *index == len => array.push_formatted(new_value),
+                    None => return Err(out_of_bounds(last)),
+                }
+            }
+            (Item::ArrayOfTables(tables), TomlPathSegment::Index(index)) => {
+                let Item::Table(table) = lua_to_item(lua, value, false)? else {
+                    return Err(LuaError::runtime(
+                        "Only tables may be placed in an array of tables",
+                    ));
+                };
+                let len = tables.len();
+                match tables.get_mut(*index) {
+                    Some(existing) => *existing = table,
+                    None if *index == len => tables.push(table),
+                    None => return Err(out_of_bounds(last)),
+                }
+            }
+            (_, TomlPathSegment::Key(key)) => return Err(not_a_table(key)),
+            (_, TomlPathSegment::Index(_)) => {
+                return Err(LuaError::runtime(format!(
+                    "Can not set index {}, the value is not an array",
+                    path_index(last)
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn remove<'lua>(&self, lua: &'lua Lua, path: &TomlPath) -> LuaResult<LuaValue<'lua>> {
+        let Some((last, parents)) = path.0.split_last() else {
+            return Err(LuaError::runtime("Can not remove the root of a document"));
+        };
+
+        let mut document = self.document.borrow_mut();
+        let mut item = document.as_item_mut();
+        for segment in parents {
+            match get_item_mut(item, segment) {
+                Some(inner) => item = inner,
+                None => return Ok(LuaValue::Nil),
+            }
+        }
+
+        let removed = match (item, last) {
+            (Item::Table(table), TomlPathSegment::Key(key)) => table.remove(key),
+            (Item::Value(Value::InlineTable(table)), TomlPathSegment::Key(key)) => {
+                table.remove(key).map(Item::Value)
+            }
+            (Item::Value(Value::Array(array)), TomlPathSegment::Index(index))
+                if *index < array.len() =>
+            {
+                Some(Item::Value(array.remove(*index)))
+            }
+            (Item::ArrayOfTables(tables), TomlPathSegment::Index(index))
+                if *index < tables.len() =>
+            {
+                let removed = tables.get(*index).cloned().map(Item::Table);
+                tables.remove(*index);
+                removed
+            }
+            _ => None,
+        };
+
+        match removed {
+            Some(item) => item_to_lua(lua, &item),
+            None => Ok(LuaValue::Nil),
+        }
+    }
+}
+
+fn path_index(segment: &TomlPathSegment) -> String {
+    match segment {
+        TomlPathSegment::Key(key) => key.clone(),
+        TomlPathSegment::Index(index) => (index + 1).to_string(),
+    }
+}
+
+fn not_a_table(key: &str) -> LuaError {
+    LuaError::runtime(format!("Can not set key '{key}', the value is not a table"))
+}
+
+fn out_of_bounds(segment: &TomlPathSegment) -> LuaError {
+    LuaError::runtime(format!("Index {} is out of bounds", path_index(segment)))
+}
+
+impl LuaUserData for TomlDocument {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "TomlDocument");
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("get", |lua, this, path: TomlPath| this.get(lua, &path));
+        methods.add_method("set", |lua, this, (path, value): (TomlPath, LuaValue)| {
+            this.set(lua, &path, value)
+        });
+        methods.add_method("remove", |lua, this, path: TomlPath| {
+            this.remove(lua, &path)
+        });
+        methods.add_method("toString", |_, this, ()| {
+            Ok(this.document.borrow().to_string())
+        });
+        methods.add_meta_method(LuaMetaMethod::ToString, |_, this, ()| {
+            Ok(this.document.borrow().to_string())
+        });
+    }
+}
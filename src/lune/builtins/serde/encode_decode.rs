@@ -1,25 +1,76 @@
 use bstr::{BString, ByteSlice};
 use mlua::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize};
 
+use ciborium::Value as CborValue;
+use rmpv::Value as MsgPackValue;
 use serde_json::Value as JsonValue;
 use serde_yaml::Value as YamlValue;
 use toml::Value as TomlValue;
 
-const LUA_SERIALIZE_OPTIONS: LuaSerializeOptions = LuaSerializeOptions::new()
+use super::{
+    csv::{csv_delimiter, decode_csv, encode_csv, CsvHeader},
+    ini::{decode_ini, encode_ini},
+    ordered::OrderedValue,
+    xml::{decode_xml, encode_xml},
+};
+
+pub(super) const LUA_SERIALIZE_OPTIONS: LuaSerializeOptions = LuaSerializeOptions::new()
     .set_array_metatable(false)
     .serialize_none_to_null(false)
     .serialize_unit_to_null(false);
 
-const LUA_DESERIALIZE_OPTIONS: LuaDeserializeOptions = LuaDeserializeOptions::new()
+pub(super) const LUA_DESERIALIZE_OPTIONS: LuaDeserializeOptions = LuaDeserializeOptions::new()
     .sort_keys(true)
     .deny_recursive_tables(false)
     .deny_unsupported_types(true);
 
+/**
+    Converts a lua value into any deserializable value, using the same conversion as `serde.encode`.
+
+    Values containing ordered tables keep the order of their keys, other tables have their keys sorted.
+*/
+pub fn lua_value_to<T: DeserializeOwned>(lua: &Lua, value: LuaValue) -> LuaResult<T> {
+    lua_value_to_with(lua, value, EmptyTableFormat::default())
+}
+
+/**
+    Converts a lua value into any deserializable value, same as [`lua_value_to`],
+    but converting empty tables that have not been marked into the given format.
+*/
+pub fn lua_value_to_with<T: DeserializeOwned>(
+    lua: &Lua,
+    value: LuaValue,
+    empty_tables: EmptyTableFormat,
+) -> LuaResult<T> {
+    let ordered = OrderedValue::from_lua_value(lua, value, empty_tables)?;
+    T::deserialize(ordered).into_lua_err()
+}
+
+/**
+    Converts a lua value into a json value, using the same conversion as `serde.encode`.
+*/
+pub fn lua_value_to_json(lua: &Lua, value: LuaValue) -> LuaResult<JsonValue> {
+    lua_value_to(lua, value)
+}
+
+/**
+    Converts a json value into a lua value, using the same conversion as `serde.decode`.
+*/
+pub fn json_to_lua_value<'lua>(lua: &'lua Lua, value: &JsonValue) -> LuaResult<LuaValue<'lua>> {
+    lua.to_value_with(value, LUA_SERIALIZE_OPTIONS)
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum EncodeDecodeFormat {
     Json,
     Yaml,
     Toml,
+    MsgPack,
+    Cbor,
+    Csv,
+    Ini,
+    Xml,
 }
 
 impl<'lua> FromLua<'lua> for EncodeDecodeFormat {
@@ -29,11 +80,16 @@ impl<'lua> FromLua<'lua> for EncodeDecodeFormat {
                 "json" => Ok(Self::Json),
                 "yaml" => Ok(Self::Yaml),
                 "toml" => Ok(Self::Toml),
+                "msgpack" => Ok(Self::MsgPack),
+                "cbor" => Ok(Self::Cbor),
+                "csv" => Ok(Self::Csv),
+                "ini" => Ok(Self::Ini),
+                "xml" => Ok(Self::Xml),
                 kind => Err(LuaError::FromLuaConversionError {
                     from: value.type_name(),
                     to: "EncodeDecodeFormat",
                     message: Some(format!(
-                        "Invalid format '{kind}', valid formats are:  json, yaml, toml"
+                        "Invalid format '{kind}', valid formats are:  json, yaml, toml, msgpack, cbor, csv, ini, xml"
                     )),
                 }),
             }
@@ -47,10 +103,153 @@ impl<'lua> FromLua<'lua> for EncodeDecodeFormat {
     }
 }
 
+/**
+    The format that empty tables which have not been marked
+    using `serde.array` or `serde.object` are encoded as.
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyTableFormat {
+    #[default]
+    Object,
+    Array,
+}
+
+impl<'lua> FromLua<'lua> for EmptyTableFormat {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        if let LuaValue::String(s) = &value {
+            match s.to_string_lossy().to_ascii_lowercase().trim() {
+                "object" => Ok(Self::Object),
+                "array" => Ok(Self::Array),
+                kind => Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "EmptyTableFormat",
+                    message: Some(format!(
+                        "Invalid empty table format '{kind}', valid formats are:  object, array"
+                    )),
+                }),
+            }
+        } else {
+            Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "EmptyTableFormat",
+                message: None,
+            })
+        }
+    }
+}
+
+/**
+    Options for encoding, given either as a boolean for `pretty`, or as a table of options.
+*/
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    pub pretty: bool,
+    pub empty_tables: EmptyTableFormat,
+    pub header: CsvHeader,
+    pub delimiter: u8,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            pretty: false,
+            empty_tables: EmptyTableFormat::default(),
+            header: CsvHeader::default(),
+            delimiter: b',',
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for EncodeOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::Boolean(pretty) => Ok(Self {
+                pretty,
+                ..Self::default()
+            }),
+            LuaValue::Table(t) => Ok(Self {
+                pretty: t.get::<_, Option<bool>>("pretty")?.unwrap_or_default(),
+                empty_tables: t.get::<_, Option<_>>("emptyTables")?.unwrap_or_default(),
+                header: t.get("header")?,
+                delimiter: csv_delimiter(t.get("delimiter")?)?,
+            }),
+            value => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "EncodeOptions",
+                message: Some(String::from("Expected a boolean or a table of options")),
+            }),
+        }
+    }
+}
+
+/**
+    Options for decoding, given either as a boolean for `preserveOrder`, or as a table of options.
+*/
 #[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+    pub preserve_order: bool,
+    pub preserve_null: bool,
+    pub mark_tables: bool,
+    pub header: bool,
+    pub delimiter: u8,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            preserve_order: false,
+            preserve_null: false,
+            mark_tables: false,
+            header: true,
+            delimiter: b',',
+        }
+    }
+}
+
+impl DecodeOptions {
+    /**
+        Checks if any of the options are enabled, in which case decoded values must be converted
+        using [`OrderedValue::into_lua_with`] instead of the builtin `mlua` conversion.
+    */
+    fn requires_ordered_value(self) -> bool {
+        self.preserve_order || self.preserve_null || self.mark_tables
+    }
+}
+
+impl<'lua> FromLua<'lua> for DecodeOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Nil => Ok(Self::default()),
+            LuaValue::Boolean(preserve_order) => Ok(Self {
+                preserve_order,
+                ..Self::default()
+            }),
+            LuaValue::Table(t) => Ok(Self {
+                preserve_order: t
+                    .get::<_, Option<bool>>("preserveOrder")?
+                    .unwrap_or_default(),
+                preserve_null: t
+                    .get::<_, Option<bool>>("preserveNull")?
+                    .unwrap_or_default(),
+                mark_tables: t.get::<_, Option<bool>>("markTables")?.unwrap_or_default(),
+                header: t.get::<_, Option<bool>>("header")?.unwrap_or(true),
+                delimiter: csv_delimiter(t.get("delimiter")?)?,
+            }),
+            value => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "DecodeOptions",
+                message: Some(String::from("Expected a boolean or a table of options")),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct EncodeDecodeConfig {
     pub format: EncodeDecodeFormat,
-    pub pretty: bool,
+    pub encode: EncodeOptions,
+    pub decode: DecodeOptions,
 }
 
 impl EncodeDecodeConfig {
@@ -59,30 +258,55 @@ impl EncodeDecodeConfig {
         lua: &'lua Lua,
         value: LuaValue<'lua>,
     ) -> LuaResult<LuaString<'lua>> {
+        let empty_tables = self.encode.empty_tables;
         let bytes = match self.format {
             EncodeDecodeFormat::Json => {
-                let serialized: JsonValue = lua.from_value_with(value, LUA_DESERIALIZE_OPTIONS)?;
-                if self.pretty {
+                let serialized: JsonValue = lua_value_to_with(lua, value, empty_tables)?;
+                if self.encode.pretty {
                     serde_json::to_vec_pretty(&serialized).into_lua_err()?
                 } else {
                     serde_json::to_vec(&serialized).into_lua_err()?
                 }
             }
             EncodeDecodeFormat::Yaml => {
-                let serialized: YamlValue = lua.from_value_with(value, LUA_DESERIALIZE_OPTIONS)?;
+                let serialized: YamlValue = lua_value_to_with(lua, value, empty_tables)?;
                 let mut writer = Vec::with_capacity(128);
                 serde_yaml::to_writer(&mut writer, &serialized).into_lua_err()?;
                 writer
             }
             EncodeDecodeFormat::Toml => {
-                let serialized: TomlValue = lua.from_value_with(value, LUA_DESERIALIZE_OPTIONS)?;
-                let s = if self.pretty {
+                let serialized: TomlValue = lua_value_to_with(lua, value, empty_tables)?;
+                let s = if self.encode.pretty {
                     toml::to_string_pretty(&serialized).into_lua_err()?
                 } else {
                     toml::to_string(&serialized).into_lua_err()?
                 };
                 s.as_bytes().to_vec()
             }
+            EncodeDecodeFormat::MsgPack => {
+                let serialized: MsgPackValue = lua_value_to_with(lua, value, empty_tables)?;
+                let mut writer = Vec::with_capacity(128);
+                rmpv::encode::write_value(&mut writer, &serialized).into_lua_err()?;
+                writer
+            }
+            EncodeDecodeFormat::Cbor => {
+                let serialized: CborValue = lua_value_to_with(lua, value, empty_tables)?;
+                let mut writer = Vec::with_capacity(128);
+                ciborium::into_writer(&serialized, &mut writer).into_lua_err()?;
+                writer
+            }
+            EncodeDecodeFormat::Csv => {
+                let serialized: OrderedValue = lua_value_to_with(lua, value, empty_tables)?;
+                encode_csv(&serialized, &self.encode.header, self.encode.delimiter)?
+            }
+            EncodeDecodeFormat::Ini => {
+                let serialized: OrderedValue = lua_value_to_with(lua, value, empty_tables)?;
+                encode_ini(&serialized)?
+            }
+            EncodeDecodeFormat::Xml => {
+                let serialized: OrderedValue = lua_value_to_with(lua, value, empty_tables)?;
+                encode_xml(&serialized, self.encode.pretty)?
+            }
         };
         lua.create_string(bytes)
     }
@@ -92,22 +316,95 @@ impl EncodeDecodeConfig {
         match self.format {
             EncodeDecodeFormat::Json => {
                 let value: JsonValue = serde_json::from_slice(bytes).into_lua_err()?;
-                lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                if self.decode.requires_ordered_value() {
+                    OrderedValue::deserialize(value)
+                        .into_lua_err()?
+                        .into_lua_with(lua, self.decode)
+                } else {
+                    json_to_lua_value(lua, &value)
+                }
             }
             EncodeDecodeFormat::Yaml => {
                 let value: YamlValue = serde_yaml::from_slice(bytes).into_lua_err()?;
-                lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                if self.decode.requires_ordered_value() {
+                    OrderedValue::deserialize(value)
+                        .into_lua_err()?
+                        .into_lua_with(lua, self.decode)
+                } else {
+                    lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                }
             }
             EncodeDecodeFormat::Toml => {
                 if let Ok(s) = string.to_str() {
                     let value: TomlValue = toml::from_str(s).into_lua_err()?;
-                    lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                    if self.decode.requires_ordered_value() {
+                        OrderedValue::deserialize(value)
+                            .into_lua_err()?
+                            .into_lua_with(lua, self.decode)
+                    } else {
+                        lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                    }
                 } else {
                     Err(LuaError::RuntimeError(
                         "TOML must be valid utf-8".to_string(),
                     ))
                 }
             }
+            EncodeDecodeFormat::MsgPack => {
+                let mut reader = bytes;
+                let value = rmpv::decode::read_value(&mut reader).into_lua_err()?;
+                if !reader.is_empty() {
+                    return Err(LuaError::RuntimeError(
+                        "MessagePack contains trailing data after the first value".to_string(),
+                    ));
+                }
+                if self.decode.requires_ordered_value() {
+                    rmpv::ext::from_value::<OrderedValue>(value)
+                        .into_lua_err()?
+                        .into_lua_with(lua, self.decode)
+                } else {
+                    lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                }
+            }
+            EncodeDecodeFormat::Cbor => {
+                let mut reader = bytes;
+                let value: CborValue = ciborium::from_reader(&mut reader).into_lua_err()?;
+                if !reader.is_empty() {
+                    return Err(LuaError::RuntimeError(
+                        "CBOR contains trailing data after the first value".to_string(),
+                    ));
+                }
+                if self.decode.requires_ordered_value() {
+                    value
+                        .deserialized::<OrderedValue>()
+                        .into_lua_err()?
+                        .into_lua_with(lua, self.decode)
+                } else {
+                    lua.to_value_with(&value, LUA_SERIALIZE_OPTIONS)
+                }
+            }
+            EncodeDecodeFormat::Csv => {
+                decode_csv(bytes, self.decode.header, self.decode.delimiter)?
+                    .into_lua_with(lua, self.decode)
+            }
+            EncodeDecodeFormat::Ini => {
+                if let Ok(s) = string.to_str() {
+                    decode_ini(s)?.into_lua_with(lua, self.decode)
+                } else {
+                    Err(LuaError::RuntimeError(
+                        "INI must be valid utf-8".to_string(),
+                    ))
+                }
+            }
+            EncodeDecodeFormat::Xml => {
+                if let Ok(s) = string.to_str() {
+                    decode_xml(s)?.into_lua_with(lua, self.decode)
+                } else {
+                    Err(LuaError::RuntimeError(
+                        "XML must be valid utf-8".to_string(),
+                    ))
+                }
+            }
         }
     }
 }
@@ -116,7 +413,8 @@ impl From<EncodeDecodeFormat> for EncodeDecodeConfig {
     fn from(format: EncodeDecodeFormat) -> Self {
         Self {
             format,
-            pretty: false,
+            encode: EncodeOptions::default(),
+            decode: DecodeOptions::default(),
         }
     }
 }
@@ -125,7 +423,11 @@ impl From<(EncodeDecodeFormat, bool)> for EncodeDecodeConfig {
     fn from(value: (EncodeDecodeFormat, bool)) -> Self {
         Self {
             format: value.0,
-            pretty: value.1,
+            encode: EncodeOptions {
+                pretty: value.1,
+                ..EncodeOptions::default()
+            },
+            decode: DecodeOptions::default(),
         }
     }
 }
@@ -0,0 +1,105 @@
+use indexmap::IndexMap;
+use ini::{EscapePolicy, Ini};
+use mlua::prelude::*;
+
+use super::ordered::OrderedValue;
+
+/**
+    Decodes an ini file into a map of sections.
+
+    Keys that are not in any section are placed directly in the returned map,
+    and each section is a map of its own, placed under the name of the section.
+    All values are strings, duplicate sections are merged, and for duplicate keys the last one wins.
+
+    Backslashes are not treated as escapes, since they are commonly used in paths in ini files.
+*/
+pub fn decode_ini(text: &str) -> LuaResult<OrderedValue> {
+    let ini = Ini::load_from_str_noescape(text).into_lua_err()?;
+
+    let mut entries = Vec::new();
+    let mut sections = IndexMap::<&str, Vec<_>>::new();
+    for (section, properties) in &ini {
+        let properties = properties
+            .iter()
+            .map(|(key, value)| (text_value(key), text_value(value)));
+        match section {
+            None => entries.extend(properties),
+            Some(name) => sections.entry(name).or_default().extend(properties),
+        }
+    }
+    entries.extend(
+        sections
+            .into_iter()
+            .map(|(name, properties)| (text_value(name), OrderedValue::Map(properties))),
+    );
+
+    Ok(OrderedValue::Map(entries))
+}
+
+/**
+    Encodes a map of sections into an ini file.
+
+    Values directly in the map are written before any sections, and maps
+    inside of the map are written as sections, which can not be nested.
+*/
+pub fn encode_ini(value: &OrderedValue) -> LuaResult<Vec<u8>> {
+    let entries = match value {
+        OrderedValue::Map(entries) => entries.as_slice(),
+        OrderedValue::Array(values) if values.is_empty() => &[],
+        _ => return Err(LuaError::runtime("INI must be a table of sections")),
+    };
+
+    let mut ini = Ini::new();
+    for (key, value) in entries {
+        let key = key_to_text(key)?;
+        match value {
+            OrderedValue::Map(properties) => {
+                let mut section = ini.with_section(Some(key));
+                for (key, value) in properties {
+                    let value = value
+                        .to_text()
+                        .ok_or_else(|| LuaError::runtime("INI sections can not contain tables"))?;
+                    section.set(key_to_text(key)?, ensure_single_line(value)?);
+                }
+            }
+            OrderedValue::Array(_) => {
+                return Err(LuaError::runtime(format!(
+                    "INI value for key '{key}' can not be an array"
+                )))
+            }
+            value => {
+                let value = value.to_text().unwrap_or_default();
+                ini.with_general_section()
+                    .set(key, ensure_single_line(value)?);
+            }
+        }
+    }
+
+    let mut writer = Vec::with_capacity(128);
+    ini.write_to_policy(&mut writer, EscapePolicy::Nothing)
+        .into_lua_err()?;
+    Ok(writer)
+}
+
+fn text_value(text: &str) -> OrderedValue {
+    OrderedValue::String(text.as_bytes().to_vec())
+}
+
+fn key_to_text(key: &OrderedValue) -> LuaResult<String> {
+    let key = key
+        .to_text()
+        .ok_or_else(|| LuaError::runtime("INI keys can not be tables"))?;
+    ensure_single_line(key)
+}
+
+fn ensure_single_line(text: String) -> LuaResult<String> {
+    // NOTE: Nothing is escaped when writing, so a newline would split the value
+    if text.contains(['\n', '\r']) {
+        Err(LuaError::runtime(format!(
+            "INI keys and values can not contain newlines, got '{}'",
+            text.escape_debug()
+        )))
+    } else {
+        Ok(text)
+    }
+}
@@ -0,0 +1,83 @@
+use mlua::prelude::*;
+
+const OBJECT_METATABLE_KEY: &str = "SerdeObjectMetatable";
+
+/**
+    The kind of a table, as given by a marker set using `serde.array` or `serde.object`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableMarker {
+    Array,
+    Object,
+}
+
+/**
+    Gets the metatable used to mark tables as objects, creating it if it does not yet exist.
+
+    Arrays are marked using the array metatable from `mlua`, which means that they are also
+    encoded as arrays when converted using the builtin `mlua` deserializer, but objects need
+    a metatable of their own that only our own conversion in [`super::ordered`] knows about.
+*/
+fn object_metatable(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+    if let Some(metatable) = lua.named_registry_value::<Option<LuaTable>>(OBJECT_METATABLE_KEY)? {
+        return Ok(metatable);
+    }
+    let metatable = lua.create_table()?;
+    // NOTE: Protect the metatable the same way as the array metatable from mlua is protected
+    metatable.raw_set("__metatable", false)?;
+    lua.set_named_registry_value(OBJECT_METATABLE_KEY, &metatable)?;
+    Ok(metatable)
+}
+
+/**
+    Marks the given table as the given kind of table, so that it is always encoded as that kind.
+*/
+pub fn mark_table<'lua>(
+    lua: &'lua Lua,
+    table: LuaTable<'lua>,
+    marker: TableMarker,
+) -> LuaResult<LuaTable<'lua>> {
+    let metatable = match marker {
+        TableMarker::Array => lua.array_metatable(),
+        TableMarker::Object => object_metatable(lua)?,
+    };
+    table.set_metatable(Some(metatable));
+    Ok(table)
+}
+
+/**
+    The metatables used to mark tables, for checking the markers of
+    many tables without looking up the object metatable for each one.
+*/
+pub struct TableMarkers<'lua> {
+    array: LuaTable<'lua>,
+    object: Option<LuaTable<'lua>>,
+}
+
+impl<'lua> TableMarkers<'lua> {
+    pub fn new(lua: &'lua Lua) -> LuaResult<Self> {
+        Ok(Self {
+            array: lua.array_metatable(),
+            // NOTE: No table can have been marked as an object if the metatable was never created
+            object: lua.named_registry_value::<Option<LuaTable>>(OBJECT_METATABLE_KEY)?,
+        })
+    }
+
+    /**
+        Gets the marker of the given table, if it has been marked as an array or an object.
+    */
+    pub fn get(&self, table: &LuaTable) -> Option<TableMarker> {
+        let metatable = table.get_metatable()?;
+        if metatable == self.array {
+            Some(TableMarker::Array)
+        } else if self
+            .object
+            .as_ref()
+            .is_some_and(|object| *object == metatable)
+        {
+            Some(TableMarker::Object)
+        } else {
+            None
+        }
+    }
+}
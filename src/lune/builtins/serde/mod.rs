@@ -1,36 +1,118 @@
+use std::{cell::RefCell, rc::Rc};
+
 use bstr::BString;
 use mlua::prelude::*;
 
 pub(super) mod compress_decompress;
+pub(super) mod csv;
+pub(super) mod decode_stream;
+pub(super) mod edit_toml;
 pub(super) mod encode_decode;
+pub(super) mod ini;
+pub(super) mod markers;
+pub(super) mod ordered;
+pub(super) mod xml;
 
 use compress_decompress::{compress, decompress, CompressDecompressFormat};
-use encode_decode::{EncodeDecodeConfig, EncodeDecodeFormat};
+use decode_stream::{ensure_streamable, take_stream_state, ChunkSource, JsonLines, JsonSelector};
+use edit_toml::TomlDocument;
+use encode_decode::{DecodeOptions, EncodeDecodeConfig, EncodeDecodeFormat, EncodeOptions};
+use markers::{mark_table, TableMarker};
 
 use crate::lune::util::TableBuilder;
 
 pub fn create(lua: &Lua) -> LuaResult<LuaTable> {
     TableBuilder::new(lua)?
+        .with_value("null", LuaValue::NULL)?
+        .with_function("array", serde_array)?
+        .with_function("object", serde_object)?
         .with_function("encode", serde_encode)?
         .with_function("decode", serde_decode)?
+        .with_function("decodeLines", serde_decode_lines)?
+        .with_function("decodeStream", serde_decode_stream)?
+        .with_function("editToml", serde_edit_toml)?
         .with_async_function("compress", serde_compress)?
         .with_async_function("decompress", serde_decompress)?
         .build_readonly()
 }
 
+fn serde_array<'lua>(lua: &'lua Lua, table: Option<LuaTable<'lua>>) -> LuaResult<LuaTable<'lua>> {
+    let table = match table {
+        Some(table) => table,
+        None => lua.create_table()?,
+    };
+    mark_table(lua, table, TableMarker::Array)
+}
+
+fn serde_object<'lua>(lua: &'lua Lua, table: Option<LuaTable<'lua>>) -> LuaResult<LuaTable<'lua>> {
+    let table = match table {
+        Some(table) => table,
+        None => lua.create_table()?,
+    };
+    mark_table(lua, table, TableMarker::Object)
+}
+
 fn serde_encode<'lua>(
     lua: &'lua Lua,
-    (format, val, pretty): (EncodeDecodeFormat, LuaValue<'lua>, Option<bool>),
+    (format, val, options): (EncodeDecodeFormat, LuaValue<'lua>, EncodeOptions),
 ) -> LuaResult<LuaString<'lua>> {
-    let config = EncodeDecodeConfig::from((format, pretty.unwrap_or_default()));
+    let config = EncodeDecodeConfig {
+        encode: options,
+        ..EncodeDecodeConfig::from(format)
+    };
     config.serialize_to_string(lua, val)
 }
 
-fn serde_decode(lua: &Lua, (format, str): (EncodeDecodeFormat, BString)) -> LuaResult<LuaValue> {
-    let config = EncodeDecodeConfig::from(format);
+fn serde_decode(
+    lua: &Lua,
+    (format, str, options): (EncodeDecodeFormat, BString, DecodeOptions),
+) -> LuaResult<LuaValue<'_>> {
+    let config = EncodeDecodeConfig {
+        decode: options,
+        ..EncodeDecodeConfig::from(format)
+    };
     config.deserialize_from_string(lua, str)
 }
 
+fn serde_decode_lines(
+    lua: &Lua,
+    (format, source): (EncodeDecodeFormat, ChunkSource),
+) -> LuaResult<LuaFunction<'_>> {
+    ensure_streamable(format, "decodeLines")?;
+    let lines = Rc::new(RefCell::new(Some(JsonLines::new(source))));
+    lua.create_async_function(move |lua, ()| {
+        let lines = Rc::clone(&lines);
+        async move {
+            let mut state = take_stream_state(&lines, "decodeLines")?;
+            let result = state.next(lua).await;
+            lines.replace(Some(state));
+            result
+        }
+    })
+}
+
+fn serde_decode_stream(
+    lua: &Lua,
+    (format, source, path): (EncodeDecodeFormat, ChunkSource, Option<String>),
+) -> LuaResult<LuaFunction<'_>> {
+    ensure_streamable(format, "decodeStream")?;
+    let selector = JsonSelector::new(source, &path.unwrap_or_default())?;
+    let selector = Rc::new(RefCell::new(Some(selector)));
+    lua.create_async_function(move |lua, ()| {
+        let selector = Rc::clone(&selector);
+        async move {
+            let mut state = take_stream_state(&selector, "decodeStream")?;
+            let result = state.next(lua).await;
+            selector.replace(Some(state));
+            result
+        }
+    })
+}
+
+fn serde_edit_toml(_: &Lua, source: BString) -> LuaResult<TomlDocument> {
+    TomlDocument::parse(source)
+}
+
 async fn serde_compress(
     lua: &Lua,
     (format, str): (CompressDecompressFormat, BString),
@@ -0,0 +1,550 @@
+use std::{cell::RefCell, cmp::Ordering, ffi::c_void, fmt};
+
+use indexmap::IndexMap;
+use mlua::prelude::*;
+use serde::{
+    de::{
+        value::{Error as ValueError, MapDeserializer, SeqDeserializer},
+        IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+
+use super::{
+    encode_decode::{DecodeOptions, EmptyTableFormat, LUA_DESERIALIZE_OPTIONS},
+    markers::{mark_table, TableMarker, TableMarkers},
+};
+
+/**
+    A value that keeps the order of keys in maps, used as an intermediate value when encoding
+    any lua value, or when decoding into an [`OrderedTable`], since ordered tables and tables
+    marked as objects need more control over their conversion than the builtin `mlua` one gives.
+
+    Any format may be deserialized into this value, and any format may be
+    deserialized from it, since it implements both `Deserialize` and `Deserializer`.
+*/
+#[derive(Debug, Clone)]
+pub enum OrderedValue {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(Vec<u8>),
+    Array(Vec<OrderedValue>),
+    Map(Vec<(OrderedValue, OrderedValue)>),
+}
+
+impl OrderedValue {
+    /**
+        Converts a lua value into an ordered value, keeping the order of keys in any
+        [`OrderedTable`], and sorting the keys of all other tables, same as `serde.encode`.
+
+        Tables marked as arrays or objects are always converted into that kind, and empty
+        tables that have not been marked are converted into the given empty table format.
+    */
+    pub fn from_lua_value(
+        lua: &Lua,
+        value: LuaValue,
+        empty_tables: EmptyTableFormat,
+    ) -> LuaResult<Self> {
+        let markers = TableMarkers::new(lua)?;
+        let converted =
+            Self::from_lua_value_inner(lua, value, empty_tables, &markers, &mut Vec::new())?;
+        Ok(converted.unwrap_or(Self::Nil))
+    }
+
+    /**
+        Converts a single lua value, or returns `None` for tables that contain themselves.
+
+        NOTE: Tables that contain themselves are skipped, the same
+        way that the builtin `mlua` conversion would skip them
+    */
+    fn from_lua_value_inner(
+        lua: &Lua,
+        value: LuaValue,
+        empty_tables: EmptyTableFormat,
+        markers: &TableMarkers,
+        ancestors: &mut Vec<*const c_void>,
+    ) -> LuaResult<Option<Self>> {
+        let pointer = value.to_pointer();
+        let converted = match value {
+            LuaValue::Table(_) | LuaValue::UserData(_) if ancestors.contains(&pointer) => {
+                return Ok(None);
+            }
+            LuaValue::Table(t) => {
+                ancestors.push(pointer);
+                let marker = markers.get(&t);
+                let is_array = match marker {
+                    Some(marker) => marker == TableMarker::Array,
+                    None if t.raw_len() > 0 => true,
+                    None if t.is_empty() => empty_tables == EmptyTableFormat::Array,
+                    None => false,
+                };
+                if is_array {
+                    let mut values = Vec::new();
+                    for value in t.sequence_values::<LuaValue>() {
+                        let value = Self::from_lua_value_inner(
+                            lua,
+                            value?,
+                            empty_tables,
+                            markers,
+                            ancestors,
+                        )?;
+                        values.extend(value);
+                    }
+                    Self::Array(values)
+                } else {
+                    let mut entries = Vec::new();
+                    for pair in t.pairs::<LuaValue, LuaValue>() {
+                        let (key, value) = pair?;
+                        let key =
+                            Self::from_lua_value_inner(lua, key, empty_tables, markers, ancestors)?;
+                        let value = Self::from_lua_value_inner(
+                            lua,
+                            value,
+                            empty_tables,
+                            markers,
+                            ancestors,
+                        )?;
+                        if let (Some(key), Some(value)) = (key, value) {
+                            entries.push((key, value));
+                        }
+                    }
+                    entries.sort_by(|(a, _), (b, _)| a.sort_cmp(b));
+                    // NOTE: Tables marked as objects are often made from arrays, and most
+                    // formats only allow string keys in objects, so number keys are converted
+                    if marker == Some(TableMarker::Object) {
+                        for (key, _) in &mut entries {
+                            match key {
+                                Self::Integer(i) => *key = Self::String(i.to_string().into_bytes()),
+                                Self::Number(n) => *key = Self::String(n.to_string().into_bytes()),
+                                _ => {}
+                            }
+                        }
+                    }
+                    Self::Map(entries)
+                }
+            }
+            LuaValue::UserData(ud) if ud.is::<OrderedTable>() => {
+                ancestors.push(pointer);
+                let this = ud.borrow::<OrderedTable>()?;
+                let mut entries = Vec::new();
+                for (key, value) in this.entries.borrow().iter() {
+                    let value = lua.registry_value::<LuaValue>(value)?;
+                    let value =
+                        Self::from_lua_value_inner(lua, value, empty_tables, markers, ancestors)?;
+                    if let Some(value) = value {
+                        entries.push((Self::from(key.clone()), value));
+                    }
+                }
+                Self::Map(entries)
+            }
+            value => {
+                return lua
+                    .from_value_with(value, LUA_DESERIALIZE_OPTIONS)
+                    .map(Some)
+            }
+        };
+        ancestors.pop();
+        Ok(Some(converted))
+    }
+
+    /**
+        Converts this value into a lua value, using the given decoding options.
+
+        Maps are converted into [`OrderedTable`]s if the order of keys should be preserved,
+        and nulls are converted into the `serde.null` sentinel if nulls should be preserved.
+    */
+    pub fn into_lua_with(self, lua: &Lua, options: DecodeOptions) -> LuaResult<LuaValue<'_>> {
+        Ok(match self {
+            Self::Nil if options.preserve_null => LuaValue::NULL,
+            Self::Nil => LuaValue::Nil,
+            Self::Boolean(b) => LuaValue::Boolean(b),
+            Self::Integer(i) => i.into_lua(lua)?,
+            Self::Number(n) => LuaValue::Number(n),
+            Self::String(s) => LuaValue::String(lua.create_string(s)?),
+            Self::Array(values) => {
+                let table = lua.create_table_with_capacity(values.len(), 0)?;
+                for (index, value) in values.into_iter().enumerate() {
+                    table.raw_set(index + 1, value.into_lua_with(lua, options)?)?;
+                }
+                if options.mark_tables {
+                    mark_table(lua, table, TableMarker::Array)?.into_lua(lua)?
+                } else {
+                    LuaValue::Table(table)
+                }
+            }
+            Self::Map(entries) if options.preserve_order => {
+                let mut map = IndexMap::with_capacity(entries.len());
+                for (key, value) in entries {
+                    let value = value.into_lua_with(lua, options)?;
+                    if value.is_nil() {
+                        continue;
+                    }
+                    map.insert(
+                        OrderedKey::try_from(key)?,
+                        lua.create_registry_value(value)?,
+                    );
+                }
+                LuaValue::UserData(lua.create_userdata(OrderedTable {
+                    entries: RefCell::new(map),
+                })?)
+            }
+            Self::Map(entries) => {
+                let table = lua.create_table_with_capacity(0, entries.len())?;
+                for (key, value) in entries {
+                    let value = value.into_lua_with(lua, options)?;
+                    if value.is_nil() {
+                        continue;
+                    }
+                    table.raw_set(key.into_lua_with(lua, options)?, value)?;
+                }
+                if options.mark_tables {
+                    mark_table(lua, table, TableMarker::Object)?.into_lua(lua)?
+                } else {
+                    LuaValue::Table(table)
+                }
+            }
+        })
+    }
+
+    /**
+        Converts this value into text, for formats where all values are text, such as csv.
+
+        Returns `None` for arrays and maps, which can not be converted into text.
+    */
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            Self::Nil => Some(String::new()),
+            Self::Boolean(b) => Some(b.to_string()),
+            Self::Integer(i) => Some(i.to_string()),
+            Self::Number(n) => Some(n.to_string()),
+            Self::String(s) => Some(String::from_utf8_lossy(s).into_owned()),
+            Self::Array(_) | Self::Map(_) => None,
+        }
+    }
+
+    /**
+        Gets the value for the given string key, if this value is a map.
+    */
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Map(entries) => entries.iter().find_map(|(k, v)| match k {
+                Self::String(s) if s == key.as_bytes() => Some(v),
+                _ => None,
+            }),
+            _ => None,
+        }
+    }
+
+    fn sort_cmp(&self, other: &Self) -> Ordering {
+        fn rank(value: &OrderedValue) -> u8 {
+            match value {
+                OrderedValue::Nil => 0,
+                OrderedValue::Boolean(_) => 1,
+                OrderedValue::Integer(_) | OrderedValue::Number(_) => 2,
+                OrderedValue::String(_) => 3,
+                OrderedValue::Array(_) | OrderedValue::Map(_) => 4,
+            }
+        }
+        let number = |value: &Self| match value {
+            #[allow(clippy::cast_precision_loss)]
+            Self::Integer(i) => *i as f64,
+            Self::Number(n) => *n,
+            _ => 0.0,
+        };
+        match (self, other) {
+            (Self::Boolean(a), Self::Boolean(b)) => a.cmp(b),
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Integer(_) | Self::Number(_), Self::Integer(_) | Self::Number(_)) => {
+                number(self)
+                    .partial_cmp(&number(other))
+                    .unwrap_or(Ordering::Equal)
+            }
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(OrderedValueVisitor)
+    }
+}
+
+struct OrderedValueVisitor;
+
+impl<'de> Visitor<'de> for OrderedValueVisitor {
+    type Value = OrderedValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(OrderedValue::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(OrderedValue::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        #[allow(clippy::cast_precision_loss)]
+        Ok(i64::try_from(v).map_or(OrderedValue::Number(v as f64), OrderedValue::Integer))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(OrderedValue::Number(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(OrderedValue::String(v.as_bytes().to_vec()))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(OrderedValue::String(v.to_vec()))
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(OrderedValue::Nil)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(OrderedValue::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        OrderedValue::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        OrderedValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(OrderedValue::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(OrderedValue::Map(entries))
+    }
+}
+
+impl<'de> Deserializer<'de> for OrderedValue {
+    type Error = ValueError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::Nil => visitor.visit_unit(),
+            Self::Boolean(b) => visitor.visit_bool(b),
+            Self::Integer(i) => visitor.visit_i64(i),
+            Self::Number(n) => visitor.visit_f64(n),
+            Self::String(s) => match String::from_utf8(s) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+            },
+            Self::Array(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Self::Map(entries) => visitor.visit_map(MapDeserializer::new(entries.into_iter())),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self {
+            Self::Nil => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ValueError> for OrderedValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+/**
+    A key in an [`OrderedTable`] - only strings, numbers and booleans may be used as keys.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OrderedKey {
+    Boolean(bool),
+    Integer(i64),
+    Number(u64),
+    String(Vec<u8>),
+}
+
+impl OrderedKey {
+    fn from_number(n: f64) -> LuaResult<Self> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        if n.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(&n) {
+            Ok(Self::Integer(n as i64))
+        } else if n.is_nan() {
+            Err(LuaError::runtime("Ordered table keys must not be NaN"))
+        } else {
+            Ok(Self::Number(n.to_bits()))
+        }
+    }
+}
+
+impl TryFrom<OrderedValue> for OrderedKey {
+    type Error = LuaError;
+
+    fn try_from(value: OrderedValue) -> LuaResult<Self> {
+        match value {
+            OrderedValue::Boolean(b) => Ok(Self::Boolean(b)),
+            OrderedValue::Integer(i) => Ok(Self::Integer(i)),
+            OrderedValue::Number(n) => Self::from_number(n),
+            OrderedValue::String(s) => Ok(Self::String(s)),
+            _ => Err(LuaError::runtime(
+                "Ordered table keys must be strings, numbers or booleans",
+            )),
+        }
+    }
+}
+
+impl From<OrderedKey> for OrderedValue {
+    fn from(key: OrderedKey) -> Self {
+        match key {
+            OrderedKey::Boolean(b) => Self::Boolean(b),
+            OrderedKey::Integer(i) => Self::Integer(i),
+            OrderedKey::Number(n) => Self::Number(f64::from_bits(n)),
+            OrderedKey::String(s) => Self::String(s),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for OrderedKey {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match value {
+            LuaValue::Boolean(b) => Ok(Self::Boolean(b)),
+            LuaValue::Integer(i) => Ok(Self::Integer(i64::from(i))),
+            LuaValue::Number(n) => Self::from_number(n),
+            LuaValue::String(s) => Ok(Self::String(s.as_bytes().to_vec())),
+            value => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "OrderedKey",
+                message: Some(String::from(
+                    "Ordered table keys must be strings, numbers or booleans",
+                )),
+            }),
+        }
+    }
+}
+
+impl<'lua> IntoLua<'lua> for OrderedKey {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match self {
+            Self::Boolean(b) => Ok(LuaValue::Boolean(b)),
+            Self::Integer(i) => i.into_lua(lua),
+            Self::Number(n) => Ok(LuaValue::Number(f64::from_bits(n))),
+            Self::String(s) => Ok(LuaValue::String(lua.create_string(s)?)),
+        }
+    }
+}
+
+/**
+    A table that keeps its keys in the order they were inserted, returned when
+    decoding with `preserveOrder` set, and encoded with the same order of keys.
+
+    Keys that are assigned to again keep their position, keys that are assigned
+    `nil` are removed, and new keys are always added at the end of the table.
+*/
+#[derive(Debug, Default)]
+pub struct OrderedTable {
+    entries: RefCell<IndexMap<OrderedKey, LuaRegistryKey>>,
+}
+
+impl OrderedTable {
+    fn get<'lua>(&self, lua: &'lua Lua, key: &OrderedKey) -> LuaResult<LuaValue<'lua>> {
+        match self.entries.borrow().get(key) {
+            Some(value) => lua.registry_value(value),
+            None => Ok(LuaValue::Nil),
+        }
+    }
+
+    fn set(&self, lua: &Lua, key: OrderedKey, value: LuaValue) -> LuaResult<()> {
+        let mut entries = self.entries.borrow_mut();
+        if value.is_nil() {
+            entries.shift_remove(&key);
+        } else if let Some(existing) = entries.get_mut(&key) {
+            lua.replace_registry_value(existing, value)?;
+        } else {
+            entries.insert(key, lua.create_registry_value(value)?);
+        }
+        Ok(())
+    }
+
+    fn next<'lua>(
+        &self,
+        lua: &'lua Lua,
+        previous: LuaValue<'lua>,
+    ) -> LuaResult<LuaMultiValue<'lua>> {
+        let entries = self.entries.borrow();
+        let index = match previous {
+            LuaValue::Nil => 0,
+            previous => {
+                let previous = OrderedKey::from_lua(previous, lua)?;
+                match entries.get_index_of(&previous) {
+                    Some(index) => index + 1,
+                    None => return Ok(LuaMultiValue::new()),
+                }
+            }
+        };
+        match entries.get_index(index) {
+            Some((key, value)) => {
+                let value = lua.registry_value::<LuaValue>(value)?;
+                (key.clone(), value).into_lua_multi(lua)
+            }
+            None => Ok(LuaMultiValue::new()),
+        }
+    }
+}
+
+impl LuaUserData for OrderedTable {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "OrderedTable");
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(LuaMetaMethod::Index, |lua, this, key: LuaValue| {
+            match OrderedKey::from_lua(key, lua) {
+                Ok(key) => this.get(lua, &key),
+                Err(_) => Ok(LuaValue::Nil),
+            }
+        });
+        methods.add_meta_method(
+            LuaMetaMethod::NewIndex,
+            |lua, this, (key, value): (OrderedKey, LuaValue)| this.set(lua, key, value),
+        );
+        methods.add_meta_method(LuaMetaMethod::Len, |_, this, ()| {
+            Ok(this.entries.borrow().len())
+        });
+        methods.add_meta_function(LuaMetaMethod::Iter, |lua, this: LuaAnyUserData| {
+            let next =
+                lua.create_function(|lua, (this, previous): (LuaAnyUserData, LuaValue)| {
+                    this.borrow::<OrderedTable>()?.next(lua, previous)
+                })?;
+            Ok((next, this))
+        });
+    }
+}
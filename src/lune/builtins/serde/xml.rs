@@ -0,0 +1,185 @@
+use mlua::prelude::*;
+use quick_xml::{
+    events::{BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+
+use super::ordered::OrderedValue;
+
+/**
+    An element that is currently being decoded, and has not yet been closed.
+*/
+struct OpenElement {
+    name: Vec<u8>,
+    attributes: Vec<(OrderedValue, OrderedValue)>,
+    children: Vec<OrderedValue>,
+}
+
+impl OpenElement {
+    fn into_value(self) -> OrderedValue {
+        OrderedValue::Map(vec![
+            (text_value("name"), OrderedValue::String(self.name)),
+            (text_value("attributes"), OrderedValue::Map(self.attributes)),
+            (text_value("children"), OrderedValue::Array(self.children)),
+        ])
+    }
+}
+
+/**
+    Decodes an xml document into its root element.
+
+    Each element is a map containing its `name`, a map of `attributes`, and an array of
+    `children`, where each child is either a string of text, or another element. Comments,
+    processing instructions, the xml declaration, and text that is only whitespace are skipped.
+*/
+pub fn decode_xml(text: &str) -> LuaResult<OrderedValue> {
+    let mut reader = Reader::from_str(text);
+    reader.expand_empty_elements(true);
+
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut root = None;
+    loop {
+        let child = match reader.read_event().into_lua_err()? {
+            Event::Start(start) => {
+                let attributes = start
+                    .attributes()
+                    .map(|attribute| {
+                        let attribute = attribute.into_lua_err()?;
+                        let value = attribute.unescape_value().into_lua_err()?;
+                        Ok((
+                            OrderedValue::String(attribute.key.as_ref().to_vec()),
+                            text_value(&value),
+                        ))
+                    })
+                    .collect::<LuaResult<_>>()?;
+                stack.push(OpenElement {
+                    name: start.name().as_ref().to_vec(),
+                    attributes,
+                    children: Vec::new(),
+                });
+                continue;
+            }
+            Event::End(_) => match stack.pop() {
+                Some(element) => element.into_value(),
+                None => return Err(LuaError::runtime("XML contains an unexpected end tag")),
+            },
+            Event::Text(text) => {
+                let text = text.unescape().into_lua_err()?;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                text_value(&text)
+            }
+            Event::CData(data) => OrderedValue::String(data.into_inner().into_owned()),
+            Event::Eof => break,
+            Event::Comment(_) | Event::Decl(_) | Event::PI(_) | Event::DocType(_) => continue,
+            Event::Empty(_) => unreachable!("empty elements are expanded"),
+        };
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(child),
+            None if matches!(child, OrderedValue::Map(_)) && root.is_none() => root = Some(child),
+            None if matches!(child, OrderedValue::Map(_)) => {
+                return Err(LuaError::runtime("XML must have a single root element"))
+            }
+            None => {
+                return Err(LuaError::runtime(
+                    "XML contains text outside of the root element",
+                ))
+            }
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(LuaError::runtime(
+            "XML ended before all elements were closed",
+        ));
+    }
+    root.ok_or_else(|| LuaError::runtime("XML must have a root element"))
+}
+
+/**
+    Encodes an element into an xml document, using the same structure as [`decode_xml`].
+
+    Elements without children are written as empty elements, and if
+    the output should be pretty, nested elements are indented.
+*/
+pub fn encode_xml(value: &OrderedValue, pretty: bool) -> LuaResult<Vec<u8>> {
+    let mut writer = if pretty {
+        Writer::new_with_indent(Vec::with_capacity(128), b' ', 2)
+    } else {
+        Writer::new(Vec::with_capacity(128))
+    };
+    write_element(&mut writer, value)?;
+    Ok(writer.into_inner())
+}
+
+fn write_element(writer: &mut Writer<Vec<u8>>, element: &OrderedValue) -> LuaResult<()> {
+    let Some(name) = element.get("name").and_then(|name| match name {
+        OrderedValue::String(_) => name.to_text(),
+        _ => None,
+    }) else {
+        return Err(LuaError::runtime(
+            "XML elements must be tables with a string name",
+        ));
+    };
+
+    let mut start = BytesStart::new(name.as_str());
+    match element.get("attributes") {
+        None | Some(OrderedValue::Nil) => {}
+        Some(OrderedValue::Map(attributes)) => {
+            for (key, value) in attributes {
+                let (Some(key), Some(value)) = (key.to_text(), value.to_text()) else {
+                    return Err(LuaError::runtime(format!(
+                        "XML attributes of element '{name}' can not be tables"
+                    )));
+                };
+                start.push_attribute((key.as_str(), value.as_str()));
+            }
+        }
+        Some(OrderedValue::Array(attributes)) if attributes.is_empty() => {}
+        Some(_) => {
+            return Err(LuaError::runtime(format!(
+                "XML attributes of element '{name}' must be a table"
+            )))
+        }
+    }
+
+    let children = match element.get("children") {
+        None | Some(OrderedValue::Nil) => &[],
+        Some(OrderedValue::Array(children)) => children.as_slice(),
+        Some(OrderedValue::Map(children)) if children.is_empty() => &[],
+        Some(_) => {
+            return Err(LuaError::runtime(format!(
+                "XML children of element '{name}' must be an array"
+            )))
+        }
+    };
+
+    if children.is_empty() {
+        return writer.write_event(Event::Empty(start)).into_lua_err();
+    }
+    writer.write_event(Event::Start(start)).into_lua_err()?;
+    for child in children {
+        match child {
+            OrderedValue::Map(_) => write_element(writer, child)?,
+            OrderedValue::Array(_) => {
+                return Err(LuaError::runtime(format!(
+                    "XML children of element '{name}' must be strings or elements"
+                )))
+            }
+            child => {
+                let text = child.to_text().unwrap_or_default();
+                writer
+                    .write_event(Event::Text(BytesText::new(&text)))
+                    .into_lua_err()?;
+            }
+        }
+    }
+    writer
+        .write_event(Event::End(BytesEnd::new(name)))
+        .into_lua_err()
+}
+
+fn text_value(text: &str) -> OrderedValue {
+    OrderedValue::String(text.as_bytes().to_vec())
+}
@@ -0,0 +1,51 @@
+use mlua::prelude::*;
+
+use crate::lune::util::formatting::ValueFormatConfig;
+
+/**
+    Options for `stdio.formatWithOptions`, given from Lua as `{ depth?, sortKeys? }`.
+
+    A depth of `math.huge` may be used to format tables without any depth limit.
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormatOptions(pub ValueFormatConfig);
+
+impl<'lua> FromLua<'lua> for FormatOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let value = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "FormatOptions",
+                    message: Some(format!(
+                        "Invalid format options - expected table, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+
+        let mut config = ValueFormatConfig::default();
+        if let Some(depth) = value.get::<_, Option<f64>>("depth")? {
+            if depth.is_nan() || depth < 0.0 {
+                return Err(LuaError::RuntimeError(format!(
+                    "Invalid value for option 'depth' - expected a non-negative number, got {depth}"
+                )));
+            }
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let depth = if depth.is_infinite() {
+                usize::MAX
+            } else {
+                depth as usize
+            };
+            config.max_depth = depth;
+        }
+        if let Some(sort_keys) = value.get::<_, Option<bool>>("sortKeys")? {
+            config.sort_keys = sort_keys;
+        }
+
+        Ok(Self(config))
+    }
+}
@@ -1,45 +1,150 @@
 use mlua::prelude::*;
 
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
+use console::{colors_enabled, colors_enabled_stderr, Term};
+use dialoguer::{
+    theme::{ColorfulTheme, Theme},
+    Confirm, Editor, FuzzySelect, Input, MultiSelect, Password, Select,
+};
 use mlua_luau_scheduler::LuaSpawnExt;
-use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+use tokio::io::{self, AsyncWriteExt};
 
 use crate::lune::util::{
     formatting::{
-        format_style, pretty_format_multi_value, style_from_color_str, style_from_style_str,
+        format_style, pretty_format_multi_value, pretty_format_multi_value_with_config,
+        style_from_color_str, style_from_style_str,
     },
     TableBuilder,
 };
 
+mod format;
+mod progress;
 mod prompt;
-use prompt::{PromptKind, PromptOptions, PromptResult};
+mod stdin;
+mod table;
+mod terminal;
+use format::FormatOptions;
+use progress::{ProgressIndicator, ProgressOptions};
+use prompt::{PromptArgs, PromptKind, PromptOptions, PromptResult};
+use table::{format_table, TableOptions};
+use terminal::StdioStream;
+
+/*
+    NOTE: Iterator functions called by generic for loops can not yield, so
+    the iterator given by stdio.lines reads lines in the background whenever
+    it is able to yield, and only blocks while waiting for a line when it is
+    being called by a for loop, where there is no way to avoid blocking
+*/
+const LINES_IMPL_LUA: &str = r#"
+local isyieldable, readLine, readLineBlocking = ...
+local function nextLine()
+    if isyieldable() then
+        return readLine()
+    end
+    return readLineBlocking()
+end
+return function()
+    return nextLine
+end
+"#;
 
 pub fn create(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+    let co = lua.globals().get::<_, LuaTable>("coroutine")?;
+    let stdio_lines = lua
+        .load(LINES_IMPL_LUA)
+        .set_name("stdio.lines")
+        .call::<_, LuaFunction>((
+            co.get::<_, LuaFunction>("isyieldable")?,
+            lua.create_async_function(stdio_read_line)?,
+            lua.create_function(stdio_read_line_blocking)?,
+        ))?;
+
     TableBuilder::new(lua)?
         .with_function("color", stdio_color)?
         .with_function("style", stdio_style)?
+        .with_function("colorsEnabled", stdio_colors_enabled)?
         .with_function("format", stdio_format)?
+        .with_function("formatWithOptions", stdio_format_with_options)?
+        .with_function("table", stdio_table)?
         .with_async_function("write", stdio_write)?
         .with_async_function("ewrite", stdio_ewrite)?
+        .with_async_function("read", stdio_read)?
+        .with_async_function("readLine", stdio_read_line)?
+        .with_value("lines", stdio_lines)?
         .with_async_function("readToEnd", stdio_read_to_end)?
         .with_async_function("prompt", stdio_prompt)?
+        .with_function("progress", stdio_progress)?
+        .with_function("spinner", stdio_spinner)?
+        .with_function("terminalSize", stdio_terminal_size)?
+        .with_function("isTerminal", stdio_is_terminal)?
+        .with_function("setRawMode", stdio_set_raw_mode)?
+        .with_async_function("readKey", stdio_read_key)?
         .build_readonly()
 }
 
-fn stdio_color(_: &Lua, color: String) -> LuaResult<String> {
-    let ansi_string = format_style(style_from_color_str(&color)?);
+fn stdio_color(lua: &Lua, (color, stream): (String, Option<StdioStream>)) -> LuaResult<String> {
+    let enabled = stdio_colors_enabled(lua, stream)?;
+    let ansi_string = format_style(style_from_color_str(&color)?, enabled);
     Ok(ansi_string)
 }
 
-fn stdio_style(_: &Lua, color: String) -> LuaResult<String> {
-    let ansi_string = format_style(style_from_style_str(&color)?);
+fn stdio_style(lua: &Lua, (style, stream): (String, Option<StdioStream>)) -> LuaResult<String> {
+    let enabled = stdio_colors_enabled(lua, stream)?;
+    let ansi_string = format_style(style_from_style_str(&style)?, enabled);
     Ok(ansi_string)
 }
 
+fn stdio_colors_enabled(_: &Lua, stream: Option<StdioStream>) -> LuaResult<bool> {
+    match stream.unwrap_or(StdioStream::Stdout) {
+        StdioStream::Stdout => Ok(colors_enabled()),
+        StdioStream::Stderr => Ok(colors_enabled_stderr()),
+        StdioStream::Stdin => Err(LuaError::runtime(
+            "Colors can only be checked for stdout and stderr",
+        )),
+    }
+}
+
 fn stdio_format(_: &Lua, args: LuaMultiValue) -> LuaResult<String> {
     pretty_format_multi_value(&args)
 }
 
+fn stdio_format_with_options(
+    _: &Lua,
+    (options, args): (FormatOptions, LuaMultiValue),
+) -> LuaResult<String> {
+    pretty_format_multi_value_with_config(&args, &options.0)
+}
+
+fn stdio_table(lua: &Lua, (rows, options): (LuaTable, TableOptions)) -> LuaResult<String> {
+    let tostring = lua.globals().get::<_, LuaFunction>("tostring")?;
+    let cell_to_string = |value: LuaValue| -> LuaResult<String> {
+        match value {
+            LuaValue::Nil => Ok(String::new()),
+            LuaValue::String(s) => Ok(s.to_str()?.to_string()),
+            value => tostring.call(value),
+        }
+    };
+    /*
+        Rows may be given either as lists of cells, or as dictionaries
+        keyed by header names, which is convenient for lists of records
+    */
+    let mut cells = Vec::new();
+    for row in rows.sequence_values::<LuaTable>() {
+        let row = row?;
+        let row = match &options.headers {
+            Some(headers) if row.raw_len() == 0 => headers
+                .iter()
+                .map(|header| cell_to_string(row.get(header.as_str())?))
+                .collect::<LuaResult<Vec<_>>>()?,
+            _ => row
+                .sequence_values::<LuaValue>()
+                .map(|cell| cell_to_string(cell?))
+                .collect::<LuaResult<Vec<_>>>()?,
+        };
+        cells.push(row);
+    }
+    Ok(format_table(&cells, &options))
+}
+
 async fn stdio_write(_: &Lua, s: LuaString<'_>) -> LuaResult<()> {
     let mut stdout = io::stdout();
     stdout.write_all(s.as_bytes()).await?;
@@ -54,25 +159,102 @@ async fn stdio_ewrite(_: &Lua, s: LuaString<'_>) -> LuaResult<()> {
     Ok(())
 }
 
-/*
-    FUTURE: Figure out how to expose some kind of "readLine" function using a buffered reader.
+const DEFAULT_READ_SIZE: usize = 1024;
 
-    This is a bit tricky since we would want to be able to use **both** readLine and readToEnd
-    in the same script, doing something like readLine, readLine, readToEnd from lua, and
-    having that capture the first two lines and then read the rest of the input.
-*/
+async fn stdio_read(lua: &Lua, len: Option<usize>) -> LuaResult<LuaValue<'_>> {
+    let len = len.unwrap_or(DEFAULT_READ_SIZE);
+    match lua.spawn_blocking(move || stdin::read(len)).await? {
+        Some(bytes) => Ok(LuaValue::String(lua.create_string(bytes)?)),
+        None => Ok(LuaValue::Nil),
+    }
+}
+
+async fn stdio_read_line(lua: &Lua, _: ()) -> LuaResult<LuaValue<'_>> {
+    match lua.spawn_blocking(stdin::read_line).await? {
+        Some(bytes) => Ok(LuaValue::String(lua.create_string(bytes)?)),
+        None => Ok(LuaValue::Nil),
+    }
+}
+
+fn stdio_read_line_blocking(lua: &Lua, _: ()) -> LuaResult<LuaValue<'_>> {
+    match stdin::read_line()? {
+        Some(bytes) => Ok(LuaValue::String(lua.create_string(bytes)?)),
+        None => Ok(LuaValue::Nil),
+    }
+}
 
 async fn stdio_read_to_end(lua: &Lua, _: ()) -> LuaResult<LuaString> {
-    let mut input = Vec::new();
-    let mut stdin = io::stdin();
-    stdin.read_to_end(&mut input).await?;
-    lua.create_string(&input)
+    let input = lua.spawn_blocking(stdin::read_to_end).await?;
+    lua.create_string(input)
 }
 
-async fn stdio_prompt(lua: &Lua, options: PromptOptions) -> LuaResult<PromptResult> {
-    lua.spawn_blocking(move || prompt(options))
-        .await
-        .into_lua_err()
+fn stdio_progress(_: &Lua, options: ProgressOptions) -> LuaResult<ProgressIndicator> {
+    Ok(ProgressIndicator::new_bar(options))
+}
+
+fn stdio_spinner(lua: &Lua, message: Option<String>) -> LuaResult<ProgressIndicator> {
+    Ok(ProgressIndicator::new_spinner(
+        lua,
+        message.unwrap_or_default(),
+    ))
+}
+
+fn stdio_terminal_size(lua: &Lua, _: ()) -> LuaResult<LuaValue<'_>> {
+    match terminal::terminal_size() {
+        None => Ok(LuaValue::Nil),
+        Some((rows, cols)) => TableBuilder::new(lua)?
+            .with_value("rows", rows)?
+            .with_value("cols", cols)?
+            .build_readonly()
+            .map(LuaValue::Table),
+    }
+}
+
+fn stdio_is_terminal(_: &Lua, stream: StdioStream) -> LuaResult<bool> {
+    Ok(stream.is_terminal())
+}
+
+fn stdio_set_raw_mode(_: &Lua, enabled: bool) -> LuaResult<()> {
+    if !StdioStream::Stdin.is_terminal() {
+        return Err(LuaError::runtime(
+            "Failed to set raw mode - stdin is not a terminal",
+        ));
+    }
+    terminal::set_raw_mode(enabled).into_lua_err()
+}
+
+async fn stdio_read_key(lua: &Lua, _: ()) -> LuaResult<LuaTable<'_>> {
+    let key = lua.spawn_blocking(terminal::read_key).await?;
+    terminal::key_to_table(lua, key)
+}
+
+async fn stdio_prompt(lua: &Lua, args: PromptArgs<'_>) -> LuaResult<PromptResult> {
+    let PromptArgs { options, validate } = args;
+    loop {
+        let opts = options.clone();
+        let result = lua
+            .spawn_blocking(move || prompt(opts))
+            .await
+            .into_lua_err()?;
+        let Some(validate) = &validate else {
+            return Ok(result);
+        };
+        /*
+            Validation callbacks are lua functions, and can't be called from
+            the blocking thread that the prompt runs on, so we validate the
+            result here instead and show the user the prompt again if invalid
+        */
+        let error = match validate.call_async::<_, LuaValue>(result.clone()).await? {
+            LuaValue::Nil | LuaValue::Boolean(true) => return Ok(result),
+            LuaValue::String(s) => s.to_str()?.to_string(),
+            _ => "Invalid input".to_string(),
+        };
+        let mut formatted = String::new();
+        ColorfulTheme::default()
+            .format_error(&mut formatted, &error)
+            .into_lua_err()?;
+        Term::stderr().write_line(&formatted)?;
+    }
 }
 
 fn prompt(options: PromptOptions) -> LuaResult<PromptResult> {
@@ -122,5 +304,65 @@ fn prompt(options: PromptOptions) -> LuaResult<PromptResult> {
                 }
             })
         }
+        PromptKind::FuzzySelect => {
+            let chosen = FuzzySelect::with_theme(&theme)
+                .with_prompt(options.text.unwrap_or_default())
+                .items(&options.options.expect("Missing options in prompt options"))
+                .interact_opt()
+                .into_lua_err()?;
+            Ok(match chosen {
+                Some(idx) => PromptResult::Index(idx + 1),
+                None => PromptResult::None,
+            })
+        }
+        PromptKind::Password => {
+            let mut prompt =
+                Password::with_theme(&theme).with_prompt(options.text.unwrap_or_default());
+            if let Some(confirmation) = options.confirmation {
+                prompt = prompt.with_confirmation(confirmation, "Passwords do not match");
+            }
+            let password = prompt.interact().into_lua_err()?;
+            Ok(PromptResult::String(password))
+        }
+        PromptKind::Number => {
+            let (min, max) = (options.min, options.max);
+            let mut prompt = Input::<f64>::with_theme(&theme)
+                .with_prompt(options.text.unwrap_or_default())
+                .validate_with(move |n: &f64| -> Result<(), String> {
+                    if !n.is_finite() {
+                        return Err("Input must be a finite number".to_string());
+                    }
+                    if let Some(min) = min.filter(|min| n < min) {
+                        return Err(format!("Input must be at least {min}"));
+                    }
+                    if let Some(max) = max.filter(|max| n > max) {
+                        return Err(format!("Input must be at most {max}"));
+                    }
+                    Ok(())
+                });
+            if let Some(n) = options.default_number {
+                prompt = prompt.default(n);
+            }
+            let number = prompt.interact_text().into_lua_err()?;
+            Ok(PromptResult::Number(number))
+        }
+        PromptKind::Editor => {
+            if let Some(text) = options.text {
+                let mut formatted = String::new();
+                theme.format_prompt(&mut formatted, &text).into_lua_err()?;
+                Term::stderr().write_line(&formatted)?;
+            }
+            let mut editor = Editor::new();
+            if let Some(extension) = &options.extension {
+                editor.extension(extension);
+            }
+            let edited = editor
+                .edit(&options.default_string.unwrap_or_default())
+                .into_lua_err()?;
+            Ok(match edited {
+                Some(s) => PromptResult::String(s),
+                None => PromptResult::None,
+            })
+        }
     }
 }
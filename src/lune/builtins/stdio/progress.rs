@@ -0,0 +1,254 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use console::{Style, Term};
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSpawnExt;
+
+use crate::lune::util::formatting::{style_from_color_str, style_from_style_str};
+
+const BAR_WIDTH: usize = 30;
+const BAR_FILLED: &str = "█";
+const BAR_EMPTY: &str = "░";
+
+const SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(80);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProgressKind {
+    Bar,
+    Spinner,
+}
+
+#[derive(Debug)]
+struct ProgressState {
+    kind: ProgressKind,
+    total: Option<u64>,
+    position: u64,
+    message: String,
+    style: Style,
+    frame: usize,
+    finished: bool,
+}
+
+impl ProgressState {
+    fn format(&self) -> String {
+        match self.kind {
+            ProgressKind::Spinner => {
+                let frame = if self.finished {
+                    "✔"
+                } else {
+                    SPINNER_FRAMES[self.frame % SPINNER_FRAMES.len()]
+                };
+                format!("{} {}", self.style.apply_to(frame), self.message)
+            }
+            ProgressKind::Bar => match self.total {
+                None => format!("{} {}", self.message, self.position),
+                Some(total) => {
+                    let ratio = if total == 0 {
+                        1.0
+                    } else {
+                        (self.position as f64 / total as f64).min(1.0)
+                    };
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    let filled = (ratio * BAR_WIDTH as f64).round() as usize;
+                    format!(
+                        "{}{}{}{} {}/{} ({:.0}%)",
+                        self.message,
+                        if self.message.is_empty() { "" } else { " " },
+                        self.style.apply_to(BAR_FILLED.repeat(filled)),
+                        BAR_EMPTY.repeat(BAR_WIDTH - filled),
+                        self.position,
+                        total,
+                        ratio * 100.0
+                    )
+                }
+            },
+        }
+    }
+}
+
+/**
+    A progress bar or spinner, rendered to stderr.
+
+    If stderr is not a terminal, nothing will be rendered, but the
+    progress indicator may still be used as usual from Lua.
+*/
+#[derive(Debug, Clone)]
+pub struct ProgressIndicator {
+    state: Arc<Mutex<ProgressState>>,
+    enabled: bool,
+}
+
+impl ProgressIndicator {
+    fn new(kind: ProgressKind, total: Option<u64>, message: String, style: Style) -> Self {
+        let this = Self {
+            state: Arc::new(Mutex::new(ProgressState {
+                kind,
+                total,
+                position: 0,
+                message,
+                style: style.for_stderr(),
+                frame: 0,
+                finished: false,
+            })),
+            enabled: Term::stderr().is_term(),
+        };
+        this.render();
+        this
+    }
+
+    pub fn new_bar(options: ProgressOptions) -> Self {
+        Self::new(
+            ProgressKind::Bar,
+            options.total,
+            options.message,
+            options.style,
+        )
+    }
+
+    /**
+        Creates a new spinner, which will be animated in the background until finished.
+    */
+    pub fn new_spinner(lua: &Lua, message: String) -> Self {
+        let this = Self::new(ProgressKind::Spinner, None, message, Style::new().cyan());
+        if this.enabled {
+            let inner = this.clone();
+            lua.spawn(async move {
+                loop {
+                    tokio::time::sleep(SPINNER_INTERVAL).await;
+                    let mut state = inner.state.lock().unwrap();
+                    if state.finished {
+                        break;
+                    }
+                    state.frame += 1;
+                    drop(state);
+                    inner.render();
+                }
+            })
+            .detach();
+        }
+        this
+    }
+
+    fn render(&self) {
+        if !self.enabled {
+            return;
+        }
+        let state = self.state.lock().unwrap();
+        let term = Term::stderr();
+        term.clear_line().ok();
+        term.write_str(&state.format()).ok();
+        if state.finished {
+            term.write_line("").ok();
+        }
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ProgressState)) {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return;
+        }
+        f(&mut state);
+        drop(state);
+        self.render();
+    }
+}
+
+impl LuaUserData for ProgressIndicator {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "ProgressIndicator");
+        fields.add_field_method_get("position", |_, this| {
+            Ok(this.state.lock().unwrap().position)
+        });
+        fields.add_field_method_get("finished", |_, this| {
+            Ok(this.state.lock().unwrap().finished)
+        });
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("advance", |_, this, amount: Option<u64>| {
+            this.update(|state| state.position += amount.unwrap_or(1));
+            Ok(())
+        });
+
+        methods.add_method("set", |_, this, position: u64| {
+            this.update(|state| state.position = position);
+            Ok(())
+        });
+
+        methods.add_method("setMessage", |_, this, message: String| {
+            this.update(|state| state.message = message);
+            Ok(())
+        });
+
+        methods.add_method("finish", |_, this, message: Option<String>| {
+            this.update(|state| {
+                if let Some(message) = message {
+                    state.message = message;
+                }
+                if let Some(total) = state.total {
+                    state.position = state.position.max(total);
+                }
+                state.finished = true;
+            });
+            Ok(())
+        });
+    }
+}
+
+/**
+    Options for `stdio.progress`, given from Lua as `{ total?, message?, style? }`.
+
+    The style may be any color name or style name accepted by `stdio.color` and `stdio.style`.
+*/
+pub struct ProgressOptions {
+    total: Option<u64>,
+    message: String,
+    style: Style,
+}
+
+impl<'lua> FromLua<'lua> for ProgressOptions {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let mut this = Self {
+            total: None,
+            message: String::new(),
+            style: Style::new().green(),
+        };
+        let value = match value {
+            LuaValue::Nil => return Ok(this),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "ProgressOptions",
+                    message: Some(format!(
+                        "Invalid progress options - expected table, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+
+        this.total = value.get("total")?;
+        if let Some(message) = value.get::<_, Option<String>>("message")? {
+            this.message = message;
+        }
+        if let Some(style) = value.get::<_, Option<String>>("style")? {
+            let parsed = style_from_color_str(&style).or_else(|_| style_from_style_str(&style));
+            this.style = match parsed {
+                Ok(Some(style)) => style.clone(),
+                Ok(None) => Style::new(),
+                Err(_) => {
+                    return Err(LuaError::RuntimeError(format!(
+                        "Invalid value for option 'style' - '{style}' is not a valid color or style name"
+                    )))
+                }
+            };
+        }
+
+        Ok(this)
+    }
+}
@@ -8,11 +8,28 @@ pub enum PromptKind {
     Confirm,
     Select,
     MultiSelect,
+    FuzzySelect,
+    Password,
+    Number,
+    Editor,
 }
 
 impl PromptKind {
     fn get_all() -> Vec<Self> {
-        vec![Self::Text, Self::Confirm, Self::Select, Self::MultiSelect]
+        vec![
+            Self::Text,
+            Self::Confirm,
+            Self::Select,
+            Self::MultiSelect,
+            Self::FuzzySelect,
+            Self::Password,
+            Self::Number,
+            Self::Editor,
+        ]
+    }
+
+    fn needs_options(self) -> bool {
+        matches!(self, Self::Select | Self::MultiSelect | Self::FuzzySelect)
     }
 }
 
@@ -32,6 +49,10 @@ impl fmt::Display for PromptKind {
                 Self::Confirm => "Confirm",
                 Self::Select => "Select",
                 Self::MultiSelect => "MultiSelect",
+                Self::FuzzySelect => "FuzzySelect",
+                Self::Password => "Password",
+                Self::Number => "Number",
+                Self::Editor => "Editor",
             }
         )
     }
@@ -69,6 +90,10 @@ impl<'lua> FromLua<'lua> for PromptKind {
                 "confirm" => Ok(Self::Confirm),
                 "select" => Ok(Self::Select),
                 "multiselect" => Ok(Self::MultiSelect),
+                "fuzzyselect" => Ok(Self::FuzzySelect),
+                "password" => Ok(Self::Password),
+                "number" => Ok(Self::Number),
+                "editor" => Ok(Self::Editor),
                 s => Err(LuaError::FromLuaConversionError {
                     from: "string",
                     to: "PromptKind",
@@ -92,15 +117,32 @@ impl<'lua> FromLua<'lua> for PromptKind {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct PromptOptions {
     pub kind: PromptKind,
     pub text: Option<String>,
     pub default_string: Option<String>,
     pub default_bool: Option<bool>,
+    pub default_number: Option<f64>,
     pub options: Option<Vec<String>>,
+    pub confirmation: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub extension: Option<String>,
+}
+
+/**
+    All of the arguments given to `stdio.prompt`.
+
+    The validation callback, if any, is kept separate from the rest of the
+    prompt options since those must be sent to a separate blocking thread.
+*/
+pub struct PromptArgs<'lua> {
+    pub options: PromptOptions,
+    pub validate: Option<LuaFunction<'lua>>,
 }
 
-impl<'lua> FromLuaMulti<'lua> for PromptOptions {
+impl<'lua> FromLuaMulti<'lua> for PromptArgs<'lua> {
     fn from_lua_multi(mut values: LuaMultiValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
         // Argument #1 - prompt kind (optional)
         let kind = values
@@ -115,17 +157,21 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
             .transpose()?;
         // Argument #3 - default value / options,
         // this is different per each prompt kind
-        let (default_bool, default_string, options) = match values.pop_front() {
-            None => (None, None, None),
+        let (default_bool, default_string, default_number, options) = match values.pop_front() {
+            None => (None, None, None, None),
             Some(options) => match options {
-                LuaValue::Nil => (None, None, None),
-                LuaValue::Boolean(b) => (Some(b), None, None),
+                LuaValue::Nil => (None, None, None, None),
+                LuaValue::Boolean(b) => (Some(b), None, None, None),
+                LuaValue::Integer(i) => (None, None, Some(i as f64), None),
+                LuaValue::Number(n) => (None, None, Some(n), None),
                 LuaValue::String(s) => (
                     None,
                     Some(String::from_lua(LuaValue::String(s), lua)?),
                     None,
+                    None,
                 ),
                 LuaValue::Table(t) => (
+                    None,
                     None,
                     None,
                     Some(Vec::<String>::from_lua(LuaValue::Table(t), lua)?),
@@ -134,16 +180,31 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
                     return Err(LuaError::FromLuaConversionError {
                         from: value.type_name(),
                         to: "PromptOptions",
-                        message: Some("Argument #3 must be a boolean, table, or nil".to_string()),
+                        message: Some(
+                            "Argument #3 must be a boolean, number, string, table, or nil"
+                                .to_string(),
+                        ),
                     })
                 }
             },
         };
+        // Argument #4 - extra configuration for the prompt (optional)
+        let config = match values.pop_front() {
+            None | Some(LuaValue::Nil) => None,
+            Some(LuaValue::Table(t)) => Some(t),
+            Some(value) => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "PromptOptions",
+                    message: Some("Argument #4 must be a table or nil".to_string()),
+                })
+            }
+        };
         /*
             Make sure we got the required values for the specific prompt kind:
 
             - "Confirm" requires a message to be present so the user knows what they are confirming
-            - "Select" and "MultiSelect" both require a table of options to choose from
+            - "Select", "MultiSelect" and "FuzzySelect" all require a table of options to choose from
         */
         if matches!(kind, PromptKind::Confirm) && text.is_none() {
             return Err(LuaError::FromLuaConversionError {
@@ -152,20 +213,53 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
                 message: Some("Argument #2 missing or nil".to_string()),
             });
         }
-        if matches!(kind, PromptKind::Select | PromptKind::MultiSelect) && options.is_none() {
+        if kind.needs_options() && options.is_none() {
             return Err(LuaError::FromLuaConversionError {
                 from: "nil",
                 to: "PromptOptions",
                 message: Some("Argument #3 missing or nil".to_string()),
             });
         }
+        // Extract any extra configuration, password confirmation
+        // may be given as either a custom message or simply true
+        let mut validate = None;
+        let mut confirmation = None;
+        let mut min = None;
+        let mut max = None;
+        let mut extension = None;
+        if let Some(config) = config {
+            validate = config.get::<_, Option<LuaFunction>>("validate")?;
+            confirmation = match config.get::<_, LuaValue>("confirm")? {
+                LuaValue::Nil | LuaValue::Boolean(false) => None,
+                LuaValue::Boolean(true) => Some("Confirm password".to_string()),
+                value => Some(String::from_lua(value, lua)?),
+            };
+            min = config.get("min")?;
+            max = config.get("max")?;
+            extension = config.get("extension")?;
+        }
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                return Err(LuaError::runtime(format!(
+                    "Invalid prompt config - min ({min}) must not be greater than max ({max})"
+                )));
+            }
+        }
         // All good, return the prompt options
         Ok(Self {
-            kind,
-            text,
-            default_bool,
-            default_string,
-            options,
+            options: PromptOptions {
+                kind,
+                text,
+                default_string,
+                default_bool,
+                default_number,
+                options,
+                confirmation,
+                min,
+                max,
+                extension,
+            },
+            validate,
         })
     }
 }
@@ -174,6 +268,7 @@ impl<'lua> FromLuaMulti<'lua> for PromptOptions {
 pub enum PromptResult {
     String(String),
     Boolean(bool),
+    Number(f64),
     Index(usize),
     Indices(Vec<usize>),
     None,
@@ -184,6 +279,7 @@ impl<'lua> IntoLua<'lua> for PromptResult {
         Ok(match self {
             Self::String(s) => LuaValue::String(lua.create_string(&s)?),
             Self::Boolean(b) => LuaValue::Boolean(b),
+            Self::Number(n) => LuaValue::Number(n),
             Self::Index(i) => LuaValue::Number(i as f64),
             Self::Indices(v) => v.into_lua(lua)?,
             Self::None => LuaValue::Nil,
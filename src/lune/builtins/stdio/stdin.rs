@@ -0,0 +1,55 @@
+use std::io::{self, BufRead, Read};
+
+/*
+    NOTE: All of the functions here read from the standard library stdin, which
+    is buffered globally for the entire process - this means that any of these
+    functions can be mixed freely, and no input will be lost when doing something
+    like readLine, readLine, readToEnd, which will read two lines and then the rest
+
+    Reading is blocking, and should be done on a separate thread when possible
+*/
+
+/**
+    Reads a single line from stdin, without the trailing newline.
+
+    Returns `None` if there is no more input to read.
+*/
+pub fn read_line() -> io::Result<Option<Vec<u8>>> {
+    let mut buf = Vec::new();
+    let read = io::stdin().lock().read_until(b'\n', &mut buf)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if buf.ends_with(b"\n") {
+        buf.pop();
+        if buf.ends_with(b"\r") {
+            buf.pop();
+        }
+    }
+    Ok(Some(buf))
+}
+
+/**
+    Reads at most `len` bytes from stdin, returning as soon as any input is available.
+
+    Returns `None` if there is no more input to read.
+*/
+pub fn read(len: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut stdin = io::stdin().lock();
+    let available = stdin.fill_buf()?;
+    if available.is_empty() {
+        return Ok(None);
+    }
+    let bytes = available[..len.min(available.len())].to_vec();
+    stdin.consume(bytes.len());
+    Ok(Some(bytes))
+}
+
+/**
+    Reads all of the remaining input from stdin.
+*/
+pub fn read_to_end() -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    io::stdin().lock().read_to_end(&mut buf)?;
+    Ok(buf)
+}
@@ -0,0 +1,234 @@
+use console::{measure_text_width, pad_str, Alignment};
+use mlua::prelude::*;
+
+use crate::lune::util::formatting::STYLE_BOLD;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableBorder {
+    None,
+    Ascii,
+    #[default]
+    Rounded,
+}
+
+impl TableBorder {
+    /**
+        Gets the characters used for drawing borders, in the order:
+
+        horizontal, vertical, and then the corners & junctions for
+        the top row, middle rows and bottom row, left to right.
+    */
+    fn chars(self) -> (&'static str, &'static str, [[&'static str; 3]; 3]) {
+        match self {
+            Self::None => ("", "", [[""; 3]; 3]),
+            Self::Ascii => ("-", "|", [["+"; 3]; 3]),
+            Self::Rounded => (
+                "─",
+                "│",
+                [["╭", "┬", "╮"], ["├", "┼", "┤"], ["╰", "┴", "╯"]],
+            ),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for TableBorder {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match &value {
+            LuaValue::Nil | LuaValue::Boolean(true) => Ok(Self::Rounded),
+            LuaValue::Boolean(false) => Ok(Self::None),
+            LuaValue::String(s) => match s.to_str()? {
+                "none" => Ok(Self::None),
+                "ascii" => Ok(Self::Ascii),
+                "rounded" => Ok(Self::Rounded),
+                s => Err(LuaError::RuntimeError(format!(
+                    "Invalid table border '{s}' - expected one of 'none', 'ascii', 'rounded'"
+                ))),
+            },
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "TableBorder",
+                message: Some(format!(
+                    "Invalid table border - expected boolean or string, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TableAlignment(Alignment);
+
+impl Default for TableAlignment {
+    fn default() -> Self {
+        Self(Alignment::Left)
+    }
+}
+
+impl<'lua> FromLua<'lua> for TableAlignment {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        match &value {
+            LuaValue::String(s) => match s.to_str()? {
+                "left" => Ok(Self(Alignment::Left)),
+                "center" => Ok(Self(Alignment::Center)),
+                "right" => Ok(Self(Alignment::Right)),
+                s => Err(LuaError::RuntimeError(format!(
+                    "Invalid table alignment '{s}' - expected one of 'left', 'center', 'right'"
+                ))),
+            },
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "TableAlignment",
+                message: Some(format!(
+                    "Invalid table alignment - expected string, got {}",
+                    value.type_name()
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    Options for `stdio.table`, given from Lua as `{ headers?, align?, border? }`.
+
+    Alignment may be given either as a single alignment for all
+    columns, or as a list of alignments for individual columns.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct TableOptions {
+    pub headers: Option<Vec<String>>,
+    pub align: Vec<TableAlignment>,
+    pub align_all: Option<TableAlignment>,
+    pub border: TableBorder,
+}
+
+impl TableOptions {
+    fn alignment(&self, column: usize) -> Alignment {
+        self.align
+            .get(column)
+            .copied()
+            .or(self.align_all)
+            .unwrap_or_default()
+            .0
+    }
+}
+
+impl<'lua> FromLua<'lua> for TableOptions {
+    fn from_lua(value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<Self> {
+        let value = match value {
+            LuaValue::Nil => return Ok(Self::default()),
+            LuaValue::Table(t) => t,
+            _ => {
+                return Err(LuaError::FromLuaConversionError {
+                    from: value.type_name(),
+                    to: "TableOptions",
+                    message: Some(format!(
+                        "Invalid table options - expected table, got {}",
+                        value.type_name()
+                    )),
+                })
+            }
+        };
+
+        let mut this = Self {
+            headers: value.get("headers")?,
+            border: value.get("border")?,
+            ..Default::default()
+        };
+        match value.get::<_, LuaValue>("align")? {
+            LuaValue::Nil => {}
+            LuaValue::Table(t) => this.align = Vec::from_lua(LuaValue::Table(t), lua)?,
+            v => this.align_all = Some(TableAlignment::from_lua(v, lua)?),
+        }
+
+        Ok(this)
+    }
+}
+
+/**
+    Formats the given rows of cells into a table, with the given options.
+
+    Cells may span multiple lines and contain ANSI escape sequences,
+    these are not counted towards the width of a cell when padding.
+*/
+pub fn format_table(rows: &[Vec<String>], options: &TableOptions) -> String {
+    let header = options
+        .headers
+        .as_ref()
+        .map(|headers| headers.iter().map(|h| STYLE_BOLD.apply_to(h).to_string()));
+    let header = header.map(Iterator::collect::<Vec<_>>);
+
+    let column_count = header
+        .iter()
+        .chain(rows.iter())
+        .map(Vec::len)
+        .max()
+        .unwrap_or(0);
+    let mut widths = vec![0; column_count];
+    for row in header.iter().chain(rows.iter()) {
+        for (column, cell) in row.iter().enumerate() {
+            for line in cell.lines() {
+                widths[column] = widths[column].max(measure_text_width(line));
+            }
+        }
+    }
+
+    let (horizontal, vertical, corners) = options.border.chars();
+    let bordered = options.border != TableBorder::None;
+
+    let separator = |[left, middle, right]: [&str; 3]| {
+        let segments = widths
+            .iter()
+            .map(|width| horizontal.repeat(width + 2))
+            .collect::<Vec<_>>();
+        format!("{left}{}{right}", segments.join(middle))
+    };
+
+    let format_row = |row: &[String], lines: &mut Vec<String>| {
+        let cells = (0..column_count)
+            .map(|column| {
+                row.get(column)
+                    .map(|cell| cell.lines().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let height = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        for line_index in 0..height {
+            let padded = cells
+                .iter()
+                .enumerate()
+                .map(|(column, cell_lines)| {
+                    let line = cell_lines.get(line_index).copied().unwrap_or_default();
+                    pad_str(line, widths[column], options.alignment(column), None).to_string()
+                })
+                .collect::<Vec<_>>();
+            lines.push(if bordered {
+                format!(
+                    "{vertical} {} {vertical}",
+                    padded.join(&format!(" {vertical} "))
+                )
+            } else {
+                padded.join("  ").trim_end().to_string()
+            });
+        }
+    };
+
+    let mut lines = Vec::new();
+    if bordered {
+        lines.push(separator(corners[0]));
+    }
+    if let Some(header) = &header {
+        format_row(header, &mut lines);
+        if bordered && !rows.is_empty() {
+            lines.push(separator(corners[1]));
+        }
+    }
+    for row in rows {
+        format_row(row, &mut lines);
+    }
+    if bordered {
+        lines.push(separator(corners[2]));
+    }
+
+    lines.join("\n")
+}
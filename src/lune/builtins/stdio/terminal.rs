@@ -0,0 +1,204 @@
+use std::io::{self, IsTerminal};
+
+use console::{Key, Term};
+use mlua::prelude::*;
+
+use crate::lune::util::TableBuilder;
+
+/**
+    A standard stream that may or may not be connected to a terminal.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdioStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl StdioStream {
+    pub fn is_terminal(self) -> bool {
+        match self {
+            Self::Stdin => io::stdin().is_terminal(),
+            Self::Stdout => io::stdout().is_terminal(),
+            Self::Stderr => io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl<'lua> FromLua<'lua> for StdioStream {
+    fn from_lua(value: LuaValue<'lua>, _: &'lua Lua) -> LuaResult<Self> {
+        let name = match &value {
+            LuaValue::String(s) => s.to_str()?.trim().to_ascii_lowercase(),
+            _ => String::new(),
+        };
+        match name.as_str() {
+            "stdin" => Ok(Self::Stdin),
+            "stdout" => Ok(Self::Stdout),
+            "stderr" => Ok(Self::Stderr),
+            _ => Err(LuaError::FromLuaConversionError {
+                from: value.type_name(),
+                to: "StdioStream",
+                message: Some(String::from(
+                    "Invalid stream - expected one of 'stdin', 'stdout', 'stderr'",
+                )),
+            }),
+        }
+    }
+}
+
+/**
+    Gets the size of the terminal, in rows and columns, if stdout is a terminal.
+*/
+pub fn terminal_size() -> Option<(u16, u16)> {
+    Term::stdout().size_checked()
+}
+
+/*
+    NOTE: Raw mode is kept track of globally, since the terminal is shared by the
+    entire process, and the original terminal settings must be restored on exit
+    since the terminal would otherwise remain in raw mode after Lune has exited
+*/
+#[cfg(unix)]
+mod raw {
+    use std::{io, sync::Mutex};
+
+    static ORIGINAL_TERMIOS: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    extern "C" fn restore_on_exit() {
+        set_raw_mode(false).ok();
+    }
+
+    pub fn is_raw_mode() -> bool {
+        ORIGINAL_TERMIOS.lock().unwrap().is_some()
+    }
+
+    pub fn set_raw_mode(enabled: bool) -> io::Result<()> {
+        let mut original = ORIGINAL_TERMIOS.lock().unwrap();
+        let fd = libc::STDIN_FILENO;
+        match (enabled, *original) {
+            (true, None) => {
+                let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+                if unsafe { libc::tcgetattr(fd, &mut termios) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                let saved = termios;
+                unsafe { libc::cfmakeraw(&mut termios) };
+                // Keep output processing so that newlines are still written as expected
+                termios.c_oflag = saved.c_oflag;
+                if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, &termios) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                static REGISTER_RESTORE: std::sync::Once = std::sync::Once::new();
+                REGISTER_RESTORE.call_once(|| unsafe {
+                    libc::atexit(restore_on_exit);
+                });
+                *original = Some(saved);
+            }
+            (false, Some(saved)) => {
+                if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, &saved) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                *original = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(unix))]
+mod raw {
+    use std::io;
+
+    pub fn is_raw_mode() -> bool {
+        false
+    }
+
+    pub fn set_raw_mode(_: bool) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Raw mode is not supported on this platform",
+        ))
+    }
+}
+
+pub use raw::set_raw_mode;
+
+/**
+    Reads a single key press from the terminal, without echoing it.
+
+    When in raw mode, Ctrl+C is given as a key press instead of interrupting Lune.
+*/
+pub fn read_key() -> io::Result<Key> {
+    let term = Term::stdout();
+    if raw::is_raw_mode() {
+        term.read_key_raw()
+    } else {
+        term.read_key()
+    }
+}
+
+/**
+    Converts a key press into a lua table containing the
+    name of the key, the character for it, and any modifiers.
+*/
+pub fn key_to_table(lua: &Lua, key: Key) -> LuaResult<LuaTable<'_>> {
+    let mut ctrl = false;
+    let mut alt = false;
+    let mut shift = false;
+    let mut chr = None;
+
+    let name = match key {
+        Key::ArrowLeft => "left".to_string(),
+        Key::ArrowRight => "right".to_string(),
+        Key::ArrowUp => "up".to_string(),
+        Key::ArrowDown => "down".to_string(),
+        Key::Enter => "enter".to_string(),
+        Key::Escape => "escape".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::Tab => "tab".to_string(),
+        Key::BackTab => {
+            shift = true;
+            "tab".to_string()
+        }
+        Key::Del => "delete".to_string(),
+        Key::Insert => "insert".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::CtrlC => {
+            ctrl = true;
+            "c".to_string()
+        }
+        // Terminals send control characters for Ctrl+<letter>
+        Key::Char(c @ '\x01'..='\x1a') => {
+            ctrl = true;
+            ((c as u8 - 1 + b'a') as char).to_string()
+        }
+        Key::Char(' ') => {
+            chr = Some(' ');
+            "space".to_string()
+        }
+        Key::Char(c) => {
+            chr = Some(c);
+            shift = c.is_uppercase();
+            c.to_lowercase().to_string()
+        }
+        // Terminals send an escape followed by the key for Alt+<key>
+        Key::UnknownEscSeq(seq) if seq.len() == 1 && seq[0] != '[' => {
+            alt = true;
+            shift = seq[0].is_uppercase();
+            seq[0].to_lowercase().to_string()
+        }
+        _ => "unknown".to_string(),
+    };
+
+    TableBuilder::new(lua)?
+        .with_value("key", name)?
+        .with_value("char", chr.map(String::from))?
+        .with_value("ctrl", ctrl)?
+        .with_value("alt", alt)?
+        .with_value("shift", shift)?
+        .build_readonly()
+}
@@ -0,0 +1,133 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use mlua::prelude::*;
+use tokio::sync::Notify;
+
+use crate::lune::util::threads::{ThreadStatus, ThreadTracker};
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    queue: VecDeque<LuaRegistryKey>,
+    capacity: Option<usize>,
+    closed: bool,
+}
+
+/**
+    A channel for sending values between lua threads, created using `task.channel`.
+
+    Sending to a channel that is full will wait until there is room,
+    and receiving from a channel that is empty will wait for a value.
+
+    Values are stored in the lua registry while they are in the channel.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Channel {
+    state: Rc<RefCell<ChannelState>>,
+    readable: Rc<Notify>,
+    writable: Rc<Notify>,
+}
+
+impl Channel {
+    pub fn new(capacity: Option<usize>) -> Self {
+        let this = Self::default();
+        this.state.borrow_mut().capacity = capacity;
+        this
+    }
+
+    fn try_send(&self, lua: &Lua, value: LuaValue) -> LuaResult<bool> {
+        let mut state = self.state.borrow_mut();
+        if state.closed {
+            return Err(LuaError::runtime("Can not send to a closed channel"));
+        }
+        if state
+            .capacity
+            .is_some_and(|capacity| state.queue.len() >= capacity)
+        {
+            return Ok(false);
+        }
+        state.queue.push_back(lua.create_registry_value(value)?);
+        self.readable.notify_one();
+        Ok(true)
+    }
+
+    fn try_recv<'lua>(&self, lua: &'lua Lua) -> LuaResult<Option<LuaValue<'lua>>> {
+        let mut state = self.state.borrow_mut();
+        let Some(key) = state.queue.pop_front() else {
+            return Ok(None);
+        };
+        let value = lua.registry_value(&key)?;
+        lua.remove_registry_value(key)?;
+        self.writable.notify_one();
+        Ok(Some(value))
+    }
+
+    pub async fn send(&self, lua: &Lua, value: LuaValue<'_>) -> LuaResult<()> {
+        loop {
+            if self.try_send(lua, value.clone())? {
+                return Ok(());
+            }
+            self.writable.notified().await;
+        }
+    }
+
+    pub async fn recv<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        loop {
+            if let Some(value) = self.try_recv(lua)? {
+                return Ok(value);
+            }
+            if self.state.borrow().closed {
+                return Ok(LuaValue::Nil);
+            }
+            self.readable.notified().await;
+        }
+    }
+
+    pub fn close(&self) {
+        self.state.borrow_mut().closed = true;
+        // Wake up all waiting threads so that senders can error
+        // and receivers can return nil once the channel is empty
+        self.readable.notify_waiters();
+        self.writable.notify_waiters();
+    }
+}
+
+impl LuaUserData for Channel {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "Channel");
+        fields.add_field_method_get("capacity", |_, this| Ok(this.state.borrow().capacity));
+        fields.add_field_method_get("closed", |_, this| Ok(this.state.borrow().closed));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(LuaMetaMethod::Len, |_, this, ()| {
+            Ok(this.state.borrow().queue.len())
+        });
+
+        methods.add_async_method("send", |lua, this, value: LuaValue| async move {
+            ThreadTracker::wait(
+                lua,
+                ThreadStatus::Blocked,
+                "Channel:send",
+                this.send(lua, value),
+            )?
+            .await
+        });
+
+        methods.add_async_method("recv", |lua, this, ()| async move {
+            ThreadTracker::wait(lua, ThreadStatus::Blocked, "Channel:recv", this.recv(lua))?.await
+        });
+
+        methods.add_method("trySend", |lua, this, value: LuaValue| {
+            this.try_send(lua, value)
+        });
+
+        methods.add_method("tryRecv", |lua, this, ()| {
+            Ok(this.try_recv(lua)?.unwrap_or(LuaValue::Nil))
+        });
+
+        methods.add_method("close", |_, this, ()| {
+            this.close();
+            Ok(())
+        });
+    }
+}
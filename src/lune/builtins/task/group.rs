@@ -0,0 +1,392 @@
+use std::{cell::RefCell, rc::Rc};
+
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSchedulerExt;
+use tokio::sync::{watch, Notify};
+
+use crate::lune::util::threads::{ThreadStatus, ThreadTracker};
+
+/*
+    NOTE: Errors in threads that are spawned on the scheduler are always
+    passed to the error callback of the scheduler, which would print them
+    and make Lune exit with a failure code, even if the error is handled
+    by whoever is waiting for the thread - to prevent this, all threads
+    spawned here run inside of a pcall, and errors are given as values
+
+    The result is then reported back to us by the thread itself, instead of being
+    tracked by the scheduler, since the scheduler never gives out a result for threads
+    that get cancelled, and would keep tracking them for as long as the scheduler runs
+*/
+const TRACKED_WRAPPER_LUA: &str = r#"
+local pcall = pcall
+return function(report, f, ...)
+    report(pcall(f, ...))
+end
+"#;
+
+const TRACKED_WRAPPER_KEY: &str = "__task_tracked_wrapper";
+
+pub fn create_tracked_wrapper(lua: &Lua) -> LuaResult<()> {
+    let wrapper = lua
+        .load(TRACKED_WRAPPER_LUA)
+        .set_name("task.tracked")
+        .call::<_, LuaFunction>(())?;
+    lua.set_named_registry_value(TRACKED_WRAPPER_KEY, wrapper)
+}
+
+/**
+    Wraps a function that closes threads, such as `task.cancel`,
+    to notify anyone waiting for a tracked thread after calling it.
+*/
+pub fn create_closing<'lua>(
+    lua: &'lua Lua,
+    close: LuaFunction<'lua>,
+) -> LuaResult<LuaFunction<'lua>> {
+    let close = lua.create_registry_value(close)?;
+    lua.create_function(move |lua, args: LuaMultiValue| {
+        let values = lua
+            .registry_value::<LuaFunction>(&close)?
+            .call::<_, LuaMultiValue>(args);
+        ThreadTracker::notify_closed(lua);
+        values
+    })
+}
+
+/**
+    The result of a tracked thread, reported by the thread once it has completed,
+    or a cancellation error if the thread was closed before it could complete.
+*/
+#[derive(Debug, Default)]
+struct TrackedResult {
+    value: RefCell<Option<Result<Vec<LuaRegistryKey>, LuaError>>>,
+    reported: Notify,
+}
+
+type ResultSlot = Rc<TrackedResult>;
+
+/**
+    A thread spawned on the scheduler, with its result being tracked.
+*/
+#[derive(Debug, Clone)]
+struct TrackedThread<'lua> {
+    thread: LuaThread<'lua>,
+    result: ResultSlot,
+}
+
+impl<'lua> TrackedThread<'lua> {
+    fn spawn(lua: &'lua Lua, f: LuaFunction<'lua>, args: LuaMultiValue<'lua>) -> LuaResult<Self> {
+        let wrapper = lua.named_registry_value::<LuaFunction>(TRACKED_WRAPPER_KEY)?;
+        let thread = lua.create_thread(wrapper)?;
+        let result = ResultSlot::default();
+        let report = {
+            let result = Rc::clone(&result);
+            lua.create_function(move |lua, values: LuaMultiValue| {
+                let value = result_from_values(lua, values);
+                result.value.borrow_mut().replace(value);
+                result.reported.notify_waiters();
+                Ok(())
+            })?
+        };
+        let mut args = args;
+        args.push_front(LuaValue::Function(f));
+        args.push_front(LuaValue::Function(report));
+        lua.push_thread_front(thread.clone(), args)?;
+        ThreadTracker::record(lua, &thread, 1)?;
+        Ok(Self { thread, result })
+    }
+
+    async fn result(&self, lua: &'lua Lua) -> LuaResult<LuaMultiValue<'lua>> {
+        let closed = ThreadTracker::closed(lua);
+        loop {
+            // NOTE: Notifications are only given to existing listeners,
+            // so we must start listening before checking for a result
+            let reported = self.result.reported.notified();
+            let cancelled = closed.notified();
+            if let Some(value) = self.result.value.borrow().as_ref() {
+                return match value {
+                    Ok(keys) => keys.iter().map(|key| lua.registry_value(key)).collect(),
+                    Err(e) => Err(e.clone()),
+                };
+            }
+            if self.thread.status() != LuaThreadStatus::Resumable {
+                self.result
+                    .value
+                    .borrow_mut()
+                    .replace(Err(LuaError::runtime("Thread was cancelled")));
+                continue;
+            }
+            tokio::select! {
+                () = reported => {},
+                () = cancelled => {},
+            }
+        }
+    }
+
+    fn cancel(&self, lua: &'lua Lua) {
+        // NOTE: The currently running thread can't be closed, which
+        // may happen if a child thread cancels its own task group
+        if self.thread == lua.current_thread() {
+            return;
+        }
+        if let Ok(close) = lua
+            .globals()
+            .get::<_, LuaTable>("coroutine")
+            .and_then(|co| co.get::<_, LuaFunction>("close"))
+        {
+            close.call::<_, ()>(self.thread.clone()).ok();
+        }
+    }
+}
+
+fn result_from_values(lua: &Lua, values: LuaMultiValue) -> LuaResult<Vec<LuaRegistryKey>> {
+    let mut values = values.into_iter();
+    match values.next() {
+        Some(LuaValue::Boolean(true)) => values
+            .map(|value| lua.create_registry_value(value))
+            .collect(),
+        _ => Err(error_from_value(
+            lua,
+            values.next().unwrap_or(LuaValue::Nil),
+        )),
+    }
+}
+
+fn error_from_value(lua: &Lua, value: LuaValue) -> LuaError {
+    match value {
+        LuaValue::Error(e) => e,
+        LuaValue::String(s) => LuaError::RuntimeError(s.to_string_lossy().to_string()),
+        value => {
+            let message = lua
+                .globals()
+                .get::<_, LuaFunction>("tostring")
+                .and_then(|tostring| tostring.call::<_, String>(value));
+            LuaError::RuntimeError(message.unwrap_or_else(|_| "unknown error".to_string()))
+        }
+    }
+}
+
+fn spawn_all<'lua>(
+    lua: &'lua Lua,
+    fns: Vec<LuaFunction<'lua>>,
+) -> LuaResult<Vec<TrackedThread<'lua>>> {
+    fns.into_iter()
+        .map(|f| TrackedThread::spawn(lua, f, LuaMultiValue::new()))
+        .collect()
+}
+
+fn cancel_all<'lua>(lua: &'lua Lua, threads: &[TrackedThread<'lua>]) {
+    for thread in threads {
+        thread.cancel(lua);
+    }
+}
+
+/**
+    Waits for all of the given threads to complete, returning the first value from each.
+
+    If any thread errors, all other threads are cancelled, and the error is returned.
+*/
+async fn wait_all<'lua>(
+    lua: &'lua Lua,
+    threads: &[TrackedThread<'lua>],
+) -> LuaResult<Vec<LuaValue<'lua>>> {
+    let mut pending = threads
+        .iter()
+        .enumerate()
+        .map(|(index, thread)| async move { (index, thread.result(lua).await) })
+        .collect::<FuturesUnordered<_>>();
+    let mut results = vec![LuaValue::Nil; threads.len()];
+    while let Some((index, result)) = pending.next().await {
+        match result {
+            Ok(values) => results[index] = values.into_iter().next().unwrap_or(LuaValue::Nil),
+            Err(e) => {
+                drop(pending);
+                cancel_all(lua, threads);
+                return Err(e);
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn results_to_table<'lua>(
+    lua: &'lua Lua,
+    results: Vec<LuaValue<'lua>>,
+) -> LuaResult<LuaTable<'lua>> {
+    let table = lua.create_table_with_capacity(results.len(), 0)?;
+    for (index, value) in results.into_iter().enumerate() {
+        table.raw_set(index + 1, value)?;
+    }
+    Ok(table)
+}
+
+pub async fn task_all<'lua>(
+    lua: &'lua Lua,
+    fns: Vec<LuaFunction<'lua>>,
+) -> LuaResult<LuaTable<'lua>> {
+    let threads = spawn_all(lua, fns)?;
+    let results = wait_all(lua, &threads);
+    let results = ThreadTracker::wait(lua, ThreadStatus::Blocked, "task.all", results)?.await?;
+    results_to_table(lua, results)
+}
+
+pub async fn task_race<'lua>(
+    lua: &'lua Lua,
+    fns: Vec<LuaFunction<'lua>>,
+) -> LuaResult<LuaMultiValue<'lua>> {
+    if fns.is_empty() {
+        return Err(LuaError::runtime(
+            "task.race must be given at least one function",
+        ));
+    }
+    let threads = spawn_all(lua, fns)?;
+    let mut pending = threads
+        .iter()
+        .map(|thread| thread.result(lua))
+        .collect::<FuturesUnordered<_>>();
+    let first = ThreadTracker::wait(lua, ThreadStatus::Blocked, "task.race", pending.next())?
+        .await
+        .expect("At least one thread was spawned");
+    drop(pending);
+    cancel_all(lua, &threads);
+    first
+}
+
+pub async fn task_any<'lua>(
+    lua: &'lua Lua,
+    fns: Vec<LuaFunction<'lua>>,
+) -> LuaResult<LuaMultiValue<'lua>> {
+    if fns.is_empty() {
+        return Err(LuaError::runtime(
+            "task.any must be given at least one function",
+        ));
+    }
+    let threads = spawn_all(lua, fns)?;
+    let mut pending = threads
+        .iter()
+        .map(|thread| thread.result(lua))
+        .collect::<FuturesUnordered<_>>();
+    let mut first_error = None;
+    loop {
+        let next = ThreadTracker::wait(lua, ThreadStatus::Blocked, "task.any", pending.next())?;
+        let Some(result) = next.await else {
+            break;
+        };
+        match result {
+            Ok(values) => {
+                drop(pending);
+                cancel_all(lua, &threads);
+                return Ok(values);
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(first_error.expect("At least one thread was spawned"))
+}
+
+/**
+    A group of threads, created using `task.group`.
+
+    Waiting for the group gives the results of all of its threads, in the order they were spawned,
+    and cancelling the group cancels all of its threads - if any thread errors, the group is cancelled.
+
+    Threads are stored in the lua registry, and removed once the group is garbage collected.
+*/
+#[derive(Debug)]
+pub struct TaskGroup {
+    threads: RefCell<Vec<(LuaRegistryKey, ResultSlot)>>,
+    cancelled: Rc<watch::Sender<bool>>,
+}
+
+impl TaskGroup {
+    pub fn new() -> Self {
+        Self {
+            threads: RefCell::new(Vec::new()),
+            cancelled: Rc::new(watch::Sender::new(false)),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    fn threads<'lua>(&self, lua: &'lua Lua) -> LuaResult<Vec<TrackedThread<'lua>>> {
+        self.threads
+            .borrow()
+            .iter()
+            .map(|(key, result)| {
+                Ok(TrackedThread {
+                    thread: lua.registry_value(key)?,
+                    result: Rc::clone(result),
+                })
+            })
+            .collect()
+    }
+
+    fn spawn<'lua>(
+        &self,
+        lua: &'lua Lua,
+        f: LuaFunction<'lua>,
+        args: LuaMultiValue<'lua>,
+    ) -> LuaResult<LuaThread<'lua>> {
+        if self.is_cancelled() {
+            return Err(LuaError::runtime(
+                "Can not spawn a thread in a task group that has been cancelled",
+            ));
+        }
+        let tracked = TrackedThread::spawn(lua, f, args)?;
+        let key = lua.create_registry_value(tracked.thread.clone())?;
+        self.threads.borrow_mut().push((key, tracked.result));
+        Ok(tracked.thread)
+    }
+
+    async fn wait<'lua>(&self, lua: &'lua Lua) -> LuaResult<LuaTable<'lua>> {
+        let threads = self.threads(lua)?;
+        let mut cancelled = self.cancelled.subscribe();
+        let results = wait_all(lua, &threads);
+        let results = ThreadTracker::wait(lua, ThreadStatus::Blocked, "TaskGroup:wait", results)?;
+        tokio::select! {
+            results = results => {
+                if let Err(e) = &results {
+                    // Errors cancel every thread in the group, not just the ones being waited for
+                    self.cancel(lua)?;
+                    return Err(e.clone());
+                }
+                results_to_table(lua, results?)
+            },
+            _ = cancelled.wait_for(|cancelled| *cancelled) => {
+                Err(LuaError::runtime("Task group was cancelled"))
+            },
+        }
+    }
+
+    fn cancel(&self, lua: &Lua) -> LuaResult<()> {
+        self.cancelled.send_replace(true);
+        cancel_all(lua, &self.threads(lua)?);
+        Ok(())
+    }
+}
+
+impl LuaUserData for TaskGroup {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "TaskGroup");
+        fields.add_field_method_get("cancelled", |_, this| Ok(this.is_cancelled()));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(LuaMetaMethod::Len, |_, this, ()| {
+            Ok(this.threads.borrow().len())
+        });
+
+        methods.add_method(
+            "spawn",
+            |lua, this, (f, args): (LuaFunction, LuaMultiValue)| this.spawn(lua, f, args),
+        );
+
+        methods.add_async_method("wait", |lua, this, ()| async move { this.wait(lua).await });
+
+        methods.add_method("cancel", |lua, this, ()| this.cancel(lua));
+    }
+}
@@ -5,7 +5,22 @@ use mlua::prelude::*;
 use mlua_luau_scheduler::Functions;
 use tokio::time::{self, Instant};
 
-use crate::lune::util::TableBuilder;
+use crate::lune::util::{
+    threads::{ThreadStatus, ThreadTracker, DEBUG_SCHEDULER},
+    TableBuilder,
+};
+
+mod channel;
+mod group;
+mod sync;
+mod timer;
+mod worker;
+
+use channel::Channel;
+use group::{create_closing, create_tracked_wrapper, task_all, task_any, task_race, TaskGroup};
+use sync::{Event, Mutex, Semaphore};
+use timer::{TimerHandle, TimerKind};
+use worker::{Worker, WorkerParent};
 
 const DELAY_IMPL_LUA: &str = r#"
 return defer(function(...)
@@ -14,9 +29,20 @@ return defer(function(...)
 end, ...)
 "#;
 
+const RECORD_IMPL_LUA: &str = r#"
+local f, record = ...
+return function(...)
+    local thread = f(...)
+    record(thread)
+    return thread
+end
+"#;
+
 pub fn create(lua: &Lua) -> LuaResult<LuaTable<'_>> {
     let fns = Functions::new(lua)?;
 
+    create_tracked_wrapper(lua)?;
+
     // Create wait & delay functions
     let task_wait = lua.create_async_function(wait)?;
     let task_delay_env = TableBuilder::new(lua)?
@@ -36,22 +62,83 @@ pub fn create(lua: &Lua) -> LuaResult<LuaTable<'_>> {
     let co = lua.globals().get::<_, LuaTable>("coroutine")?;
     co.set("resume", fns.resume.clone())?;
     co.set("wrap", fns.wrap.clone())?;
+    co.set("close", create_closing(lua, co.get("close")?)?)?;
+
+    // Keep track of where threads were created, for task.info, but only while
+    // debugging since recording every spawned thread is not free
+    let (task_defer, task_delay, task_spawn) = if *DEBUG_SCHEDULER {
+        (
+            create_recording(lua, "task.defer", fns.defer)?,
+            create_recording(lua, "task.delay", task_delay)?,
+            create_recording(lua, "task.spawn", fns.spawn)?,
+        )
+    } else {
+        (fns.defer, task_delay, fns.spawn)
+    };
 
     TableBuilder::new(lua)?
-        .with_value("cancel", fns.cancel)?
-        .with_value("defer", fns.defer)?
+        .with_value("cancel", create_closing(lua, fns.cancel)?)?
+        .with_value("defer", task_defer)?
         .with_value("delay", task_delay)?
-        .with_value("spawn", fns.spawn)?
+        .with_value("spawn", task_spawn)?
         .with_value("wait", task_wait)?
+        .with_function("info", |lua, ()| ThreadTracker::collect(lua))?
+        .with_function("interval", task_interval)?
+        .with_function("timeout", task_timeout)?
+        .with_function("channel", task_channel)?
+        .with_function("mutex", |_, ()| Ok(Mutex::new()))?
+        .with_function("semaphore", |_, permits: usize| Semaphore::new(permits))?
+        .with_function("event", |_, ()| Ok(Event::new()))?
+        .with_async_function("all", task_all)?
+        .with_async_function("any", task_any)?
+        .with_async_function("race", task_race)?
+        .with_function("group", |_, ()| Ok(TaskGroup::new()))?
+        .with_function("worker", Worker::new)?
+        .with_value("parent", WorkerParent::port(lua))?
         .build_readonly()
 }
 
-async fn wait(_: &Lua, secs: Option<f64>) -> LuaResult<f64> {
+fn create_recording<'lua>(
+    lua: &'lua Lua,
+    name: &'static str,
+    f: LuaFunction<'lua>,
+) -> LuaResult<LuaFunction<'lua>> {
+    // NOTE: The stack here is the record function, the
+    // recording wrapper, and then the function calling it
+    let record =
+        lua.create_function(|lua, thread: LuaThread| ThreadTracker::record(lua, &thread, 2))?;
+    lua.load(RECORD_IMPL_LUA).set_name(name).call((f, record))
+}
+
+async fn wait(lua: &Lua, secs: Option<f64>) -> LuaResult<f64> {
     let duration = Duration::from_secs_f64(secs.unwrap_or_default());
 
     let before = Instant::now();
-    time::sleep(duration).await;
+    ThreadTracker::wait(lua, ThreadStatus::Timer, "task.wait", time::sleep(duration))?.await;
     let after = Instant::now();
 
     Ok((after - before).as_secs_f64())
 }
+
+fn task_channel(_: &Lua, capacity: Option<usize>) -> LuaResult<Channel> {
+    if capacity == Some(0) {
+        return Err(LuaError::runtime(
+            "Channel capacity must be greater than zero",
+        ));
+    }
+    Ok(Channel::new(capacity))
+}
+
+fn task_interval<'lua>(
+    lua: &'lua Lua,
+    (seconds, function, args): (f64, LuaFunction<'lua>, LuaMultiValue<'lua>),
+) -> LuaResult<TimerHandle> {
+    TimerHandle::new(lua, TimerKind::Interval, seconds, function, args)
+}
+
+fn task_timeout<'lua>(
+    lua: &'lua Lua,
+    (seconds, function, args): (f64, LuaFunction<'lua>, LuaMultiValue<'lua>),
+) -> LuaResult<TimerHandle> {
+    TimerHandle::new(lua, TimerKind::Timeout, seconds, function, args)
+}
@@ -0,0 +1,211 @@
+use std::{cell::Cell, rc::Rc};
+
+use mlua::prelude::*;
+use tokio::sync::{Notify, Semaphore as TokioSemaphore};
+
+use crate::lune::util::threads::{ThreadStatus, ThreadTracker};
+
+/*
+    NOTE: Permits are acquired and then immediately forgotten here, and later
+    added back manually when released, since lua code can not hold on to the
+    permit guards that tokio gives us - this also means that permits can be
+    released from a different lua thread than the one that acquired them
+*/
+
+async fn acquire_permits(semaphore: &TokioSemaphore, count: u32) -> LuaResult<()> {
+    semaphore.acquire_many(count).await.into_lua_err()?.forget();
+    Ok(())
+}
+
+fn try_acquire_permits(semaphore: &TokioSemaphore, count: u32) -> bool {
+    match semaphore.try_acquire_many(count) {
+        Ok(permit) => {
+            permit.forget();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/**
+    A mutual exclusion lock, created using `task.mutex`.
+
+    Threads waiting to lock the mutex are resumed in the order they started waiting.
+*/
+#[derive(Debug, Clone)]
+pub struct Mutex {
+    inner: Rc<TokioSemaphore>,
+}
+
+impl Mutex {
+    pub fn new() -> Self {
+        Self {
+            inner: Rc::new(TokioSemaphore::new(1)),
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.inner.available_permits() == 0
+    }
+}
+
+impl LuaUserData for Mutex {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "Mutex");
+        fields.add_field_method_get("locked", |_, this| Ok(this.is_locked()));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("lock", |lua, this, ()| async move {
+            let acquire = acquire_permits(&this.inner, 1);
+            ThreadTracker::wait(lua, ThreadStatus::Blocked, "Mutex:lock", acquire)?.await
+        });
+
+        methods.add_method("tryLock", |_, this, ()| {
+            Ok(try_acquire_permits(&this.inner, 1))
+        });
+
+        methods.add_method("unlock", |_, this, ()| {
+            if !this.is_locked() {
+                return Err(LuaError::runtime(
+                    "Can not unlock a mutex that is not locked",
+                ));
+            }
+            this.inner.add_permits(1);
+            Ok(())
+        });
+    }
+}
+
+/**
+    A counting semaphore, created using `task.semaphore`.
+
+    Threads waiting to acquire permits are resumed in the order they started waiting.
+*/
+#[derive(Debug, Clone)]
+pub struct Semaphore {
+    inner: Rc<TokioSemaphore>,
+    held: Rc<Cell<usize>>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> LuaResult<Self> {
+        if permits > TokioSemaphore::MAX_PERMITS {
+            return Err(LuaError::runtime(format!(
+                "Semaphore can have at most {} permits",
+                TokioSemaphore::MAX_PERMITS
+            )));
+        }
+        Ok(Self {
+            inner: Rc::new(TokioSemaphore::new(permits)),
+            held: Rc::new(Cell::new(0)),
+        })
+    }
+
+    /**
+        Gets the total number of permits, including any that are currently acquired.
+    */
+    fn total_permits(&self) -> usize {
+        self.inner.available_permits() + self.held.get()
+    }
+
+    /**
+        Updates the number of permits that are currently acquired.
+    */
+    fn set_held(&self, acquired: usize, released: usize) {
+        self.held
+            .set((self.held.get() + acquired).saturating_sub(released));
+    }
+}
+
+impl LuaUserData for Semaphore {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "Semaphore");
+        fields.add_field_method_get("available", |_, this| Ok(this.inner.available_permits()));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("acquire", |lua, this, count: Option<u32>| async move {
+            let count = count.unwrap_or(1);
+            // NOTE: Waiting for more permits than the semaphore has would never finish
+            let total = this.total_permits();
+            if count as usize > total {
+                return Err(LuaError::runtime(format!(
+                    "Can not acquire {count} permits from a semaphore with {total} permits"
+                )));
+            }
+            let acquire = acquire_permits(&this.inner, count);
+            ThreadTracker::wait(lua, ThreadStatus::Blocked, "Semaphore:acquire", acquire)?.await?;
+            this.set_held(count as usize, 0);
+            Ok(())
+        });
+
+        methods.add_method("tryAcquire", |_, this, count: Option<u32>| {
+            let count = count.unwrap_or(1);
+            let acquired = try_acquire_permits(&this.inner, count);
+            if acquired {
+                this.set_held(count as usize, 0);
+            }
+            Ok(acquired)
+        });
+
+        methods.add_method("release", |_, this, count: Option<usize>| {
+            let count = count.unwrap_or(1);
+            if this.inner.available_permits() + count > TokioSemaphore::MAX_PERMITS {
+                return Err(LuaError::runtime(format!(
+                    "Semaphore can have at most {} permits",
+                    TokioSemaphore::MAX_PERMITS
+                )));
+            }
+            this.inner.add_permits(count);
+            this.set_held(0, count);
+            Ok(())
+        });
+    }
+}
+
+/**
+    An event that threads can wait for, created using `task.event`.
+
+    Setting the event resumes all threads that are currently waiting for it,
+    and any threads that wait while the event is set will resume immediately.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    set: Rc<Cell<bool>>,
+    notify: Rc<Notify>,
+}
+
+impl Event {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LuaUserData for Event {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "Event");
+        fields.add_field_method_get("isSet", |_, this| Ok(this.set.get()));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_async_method("wait", |lua, this, ()| async move {
+            if !this.set.get() {
+                let notified = this.notify.notified();
+                ThreadTracker::wait(lua, ThreadStatus::Blocked, "Event:wait", notified)?.await;
+            }
+            Ok(())
+        });
+
+        methods.add_method("set", |_, this, ()| {
+            this.set.set(true);
+            this.notify.notify_waiters();
+            Ok(())
+        });
+
+        methods.add_method("clear", |_, this, ()| {
+            this.set.set(false);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    cell::RefCell,
+    rc::{Rc, Weak},
+    time::Duration,
+};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSchedulerExt;
+use tokio::{
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+use crate::lune::util::threads::ThreadTracker;
+
+// NOTE: Deadlines that are too far away to be represented are clamped to
+// roughly 30 years from now, the same way that tokio clamps them for task.wait
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerKind {
+    Timeout,
+    Interval,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TimerCommand {
+    Reset,
+    Cancel,
+}
+
+/**
+    The callback for a timer, along with its arguments, stored in the lua registry.
+*/
+#[derive(Debug)]
+struct TimerCallback {
+    function: LuaRegistryKey,
+    args: Vec<LuaRegistryKey>,
+}
+
+impl TimerCallback {
+    fn spawn(&self, lua: &Lua) -> LuaResult<()> {
+        let function = lua.registry_value::<LuaFunction>(&self.function)?;
+        let args = self
+            .args
+            .iter()
+            .map(|key| lua.registry_value::<LuaValue>(key))
+            .collect::<LuaResult<LuaMultiValue>>()?;
+        lua.push_thread_front(function, args)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    commands: Option<mpsc::UnboundedSender<TimerCommand>>,
+    cancelled: bool,
+}
+
+/**
+    A handle to a timer, created using `task.timeout` or `task.interval`.
+
+    Timers are driven by a background task that keeps the scheduler alive until the
+    timer has finished or been cancelled - dropping the handle does not stop the timer.
+*/
+#[derive(Debug, Clone)]
+pub struct TimerHandle {
+    kind: TimerKind,
+    period: Duration,
+    callback: Rc<TimerCallback>,
+    state: Rc<RefCell<TimerState>>,
+}
+
+impl TimerHandle {
+    pub fn new(
+        lua: &Lua,
+        kind: TimerKind,
+        seconds: f64,
+        function: LuaFunction,
+        args: LuaMultiValue,
+    ) -> LuaResult<Self> {
+        if seconds.is_nan() || seconds < 0.0 {
+            return Err(LuaError::runtime(format!(
+                "Timer duration must be a non-negative number, got {seconds}"
+            )));
+        }
+        let period = Duration::try_from_secs_f64(seconds).unwrap_or(Duration::MAX);
+        if kind == TimerKind::Interval && period.is_zero() {
+            return Err(LuaError::runtime(
+                "Interval duration must be greater than zero",
+            ));
+        }
+
+        let callback = TimerCallback {
+            function: lua.create_registry_value(function)?,
+            args: args
+                .into_iter()
+                .map(|arg| lua.create_registry_value(arg))
+                .collect::<LuaResult<_>>()?,
+        };
+
+        let this = Self {
+            kind,
+            period,
+            callback: Rc::new(callback),
+            state: Rc::new(RefCell::new(TimerState::default())),
+        };
+        this.start(lua);
+        Ok(this)
+    }
+
+    fn is_active(&self) -> bool {
+        self.state.borrow().commands.is_some()
+    }
+
+    fn start(&self, lua: &Lua) {
+        let lua_inner = lua
+            .app_data_ref::<Weak<Lua>>()
+            .expect("Missing weak lua ref")
+            .upgrade()
+            .expect("Lua was dropped unexpectedly");
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.state.borrow_mut().commands.replace(tx);
+
+        ThreadTracker::spawn_source(
+            lua,
+            run_timer(
+                lua_inner,
+                self.kind,
+                self.period,
+                Rc::clone(&self.callback),
+                Rc::clone(&self.state),
+                rx,
+            ),
+        );
+    }
+
+    fn send(&self, command: TimerCommand) -> bool {
+        match &self.state.borrow().commands {
+            Some(tx) => tx.send(command).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.send(TimerCommand::Cancel);
+        let mut state = self.state.borrow_mut();
+        state.commands.take();
+        state.cancelled = true;
+    }
+
+    pub fn reset(&self, lua: &Lua) -> LuaResult<()> {
+        if self.state.borrow().cancelled {
+            return Err(LuaError::runtime(
+                "Can not reset a timer that was cancelled",
+            ));
+        }
+        // Timeouts that already fired are started again
+        if !self.send(TimerCommand::Reset) {
+            self.start(lua);
+        }
+        Ok(())
+    }
+}
+
+/*
+    NOTE: The deadline for each tick of an interval is calculated from the
+    previous deadline, not from when the previous tick actually happened,
+    which prevents any delays in waking up from accumulating over time
+
+    If we fall behind by more than a full period, for example if the scheduler was
+    blocked, any missed ticks are skipped instead of running the callback in a burst
+*/
+async fn run_timer(
+    lua: Rc<Lua>,
+    kind: TimerKind,
+    period: Duration,
+    callback: Rc<TimerCallback>,
+    state: Rc<RefCell<TimerState>>,
+    mut commands: mpsc::UnboundedReceiver<TimerCommand>,
+) {
+    let mut deadline = deadline_after(Instant::now(), period);
+    loop {
+        tokio::select! {
+            () = time::sleep_until(deadline) => {
+                callback.spawn(&lua).expect("Failed to spawn timer callback thread");
+                if kind == TimerKind::Timeout {
+                    break;
+                }
+                deadline = next_deadline(deadline, period, Instant::now());
+            }
+            command = commands.recv() => match command {
+                Some(TimerCommand::Reset) => deadline = deadline_after(Instant::now(), period),
+                Some(TimerCommand::Cancel) | None => return,
+            }
+        }
+    }
+    state.borrow_mut().commands.take();
+}
+
+fn deadline_after(start: Instant, period: Duration) -> Instant {
+    start
+        .checked_add(period)
+        .unwrap_or_else(|| start + FAR_FUTURE)
+}
+
+fn next_deadline(deadline: Instant, period: Duration, now: Instant) -> Instant {
+    let next = deadline_after(deadline, period);
+    if next > now {
+        return next;
+    }
+    if period.is_zero() {
+        return now;
+    }
+    // Skip all of the periods that were missed at once, landing on the first deadline after now
+    let missed = (now - next).as_nanos() / period.as_nanos() + 1;
+    match u64::try_from(missed * period.as_nanos()) {
+        Ok(nanos) => deadline_after(next, Duration::from_nanos(nanos)),
+        Err(_) => deadline_after(now, period),
+    }
+}
+
+impl LuaUserData for TimerHandle {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "TimerHandle");
+        fields.add_field_method_get("active", |_, this| Ok(this.is_active()));
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("cancel", |_, this, ()| {
+            this.cancel();
+            Ok(())
+        });
+
+        methods.add_method("reset", |lua, this, ()| this.reset(lua));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_deadline_on_time() {
+        let start = Instant::now();
+        let period = Duration::from_millis(10);
+        assert_eq!(next_deadline(start, period, start), start + period);
+    }
+
+    #[test]
+    fn next_deadline_skips_missed() {
+        let start = Instant::now();
+        let period = Duration::from_millis(10);
+        assert_eq!(
+            next_deadline(start, period, start + Duration::from_millis(35)),
+            start + Duration::from_millis(40)
+        );
+        assert_eq!(
+            next_deadline(start, period, start + Duration::from_millis(40)),
+            start + Duration::from_millis(50)
+        );
+    }
+
+    #[test]
+    fn next_deadline_skips_many_missed() {
+        let start = Instant::now();
+        let period = Duration::from_nanos(1);
+        let now = start + Duration::from_secs(86400);
+        assert_eq!(next_deadline(start, period, now), now + period);
+    }
+
+    #[test]
+    fn next_deadline_zero_period() {
+        let start = Instant::now();
+        let now = start + Duration::from_millis(5);
+        assert_eq!(next_deadline(start, Duration::ZERO, now), now);
+    }
+}
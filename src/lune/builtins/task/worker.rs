@@ -0,0 +1,330 @@
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::{Rc, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use mlua::{prelude::*, VmState};
+use mlua_luau_scheduler::{LuaSchedulerExt, LuaSpawnExt};
+use serde_json::Value as JsonValue;
+use tokio::sync::{mpsc, Notify};
+
+use crate::lune::{
+    util::{
+        paths::{resolve_chunk_path, runtime_cwd, set_runtime_cwd},
+        threads::ThreadTracker,
+    },
+    Runtime,
+};
+
+use super::super::serde::encode_decode::{json_to_lua_value, lua_value_to_json};
+
+/**
+    Shared state for controlling a worker, which may be used from both the
+    thread that created the worker and the thread that the worker runs on.
+*/
+#[derive(Debug, Default)]
+struct WorkerControl {
+    running: AtomicBool,
+    terminated: AtomicBool,
+    terminate: Notify,
+    exited: Notify,
+}
+
+/**
+    One end of the connection between a worker and the runtime that created it.
+
+    Messages are converted to json values when posted, and converted back into lua values
+    when received, so only values that can be encoded using `serde.encode` may be posted.
+
+    Messages are buffered until a callback is given using `onMessage`, after which
+    the port keeps its runtime alive until the other end of the connection is dropped.
+*/
+#[derive(Debug, Clone)]
+pub struct WorkerPort {
+    sender: mpsc::UnboundedSender<JsonValue>,
+    receiver: Rc<RefCell<Option<mpsc::UnboundedReceiver<JsonValue>>>>,
+    callback: Rc<RefCell<Option<LuaRegistryKey>>>,
+}
+
+/**
+    The sending & receiving halves for a port, which can be moved to another thread.
+*/
+type WorkerPortParts = (
+    mpsc::UnboundedSender<JsonValue>,
+    mpsc::UnboundedReceiver<JsonValue>,
+);
+
+impl WorkerPort {
+    fn pair() -> (WorkerPortParts, WorkerPortParts) {
+        let (parent_tx, worker_rx) = mpsc::unbounded_channel();
+        let (worker_tx, parent_rx) = mpsc::unbounded_channel();
+        ((parent_tx, parent_rx), (worker_tx, worker_rx))
+    }
+
+    fn from_parts((sender, receiver): WorkerPortParts) -> Self {
+        Self {
+            sender,
+            receiver: Rc::new(RefCell::new(Some(receiver))),
+            callback: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    fn post(&self, lua: &Lua, value: LuaValue) -> LuaResult<()> {
+        let message = lua_value_to_json(lua, value)?;
+        self.sender.send(message).map_err(|_| {
+            LuaError::runtime("Can not post a message, the other side of the worker has exited")
+        })
+    }
+
+    fn on_message(&self, lua: &Lua, callback: LuaFunction) -> LuaResult<()> {
+        self.callback
+            .borrow_mut()
+            .replace(lua.create_registry_value(callback)?);
+
+        let Some(mut receiver) = self.receiver.borrow_mut().take() else {
+            return Ok(());
+        };
+        let lua_inner = lua
+            .app_data_ref::<Weak<Lua>>()
+            .expect("Missing weak lua ref")
+            .upgrade()
+            .expect("Lua was dropped unexpectedly");
+        let callback = Rc::clone(&self.callback);
+        ThreadTracker::spawn_source(lua, async move {
+            while let Some(message) = receiver.recv().await {
+                dispatch_message(&lua_inner, &callback.borrow(), &message)
+                    .expect("Failed to spawn worker message thread");
+            }
+        });
+        Ok(())
+    }
+}
+
+fn dispatch_message(
+    lua: &Lua,
+    callback: &Option<LuaRegistryKey>,
+    message: &JsonValue,
+) -> LuaResult<()> {
+    let callback = callback.as_ref().expect("Callback is set before listening");
+    let function = lua.registry_value::<LuaFunction>(callback)?;
+    let value = json_to_lua_value(lua, message)?;
+    lua.push_thread_front(function, value)?;
+    Ok(())
+}
+
+impl LuaUserData for WorkerPort {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "WorkerPort");
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("post", |lua, this, value: LuaValue| this.post(lua, value));
+        methods.add_method("onMessage", |lua, this, callback: LuaFunction| {
+            this.on_message(lua, callback)
+        });
+    }
+}
+
+/**
+    Port for the runtime that a worker is running in, stored in the app data of the worker
+    runtime and given to lua as `task.parent` - this is not available in other runtimes.
+*/
+#[derive(Debug)]
+pub struct WorkerParent {
+    parts: RefCell<Option<WorkerPortParts>>,
+    port: RefCell<Option<WorkerPort>>,
+}
+
+impl WorkerParent {
+    /**
+        Gets the port to the parent of the current worker, if the runtime is running as a worker.
+    */
+    pub fn port(lua: &Lua) -> Option<WorkerPort> {
+        let parent = lua.app_data_ref::<WorkerParent>()?;
+        let mut port = parent.port.borrow_mut();
+        if port.is_none() {
+            let parts = parent.parts.borrow_mut().take()?;
+            port.replace(WorkerPort::from_parts(parts));
+        }
+        port.clone()
+    }
+}
+
+/**
+    A worker, created using `task.worker`.
+
+    Workers run a module in a separate runtime, on a separate thread, and can only communicate
+    with the runtime that created them by posting messages. The creating runtime is kept alive
+    until the worker has exited, either by finishing its module or by being terminated.
+*/
+#[derive(Debug)]
+pub struct Worker {
+    port: WorkerPort,
+    control: Arc<WorkerControl>,
+}
+
+impl Worker {
+    pub fn new(lua: &Lua, module_path: String) -> LuaResult<Self> {
+        let (chunk_name, contents) = read_worker_module(lua, &module_path)?;
+        let cwd = runtime_cwd(lua);
+        let args = lua
+            .app_data_ref::<Vec<String>>()
+            .map(|args| args.clone())
+            .unwrap_or_default();
+
+        let (parent_parts, worker_parts) = WorkerPort::pair();
+        let control = Arc::new(WorkerControl::default());
+        control.running.store(true, Ordering::SeqCst);
+
+        let control_inner = Arc::clone(&control);
+        thread::Builder::new()
+            .name(format!("lune-worker-{chunk_name}"))
+            .spawn(move || {
+                run_worker(
+                    control_inner,
+                    worker_parts,
+                    args,
+                    cwd,
+                    &chunk_name,
+                    contents,
+                )
+            })
+            .into_lua_err()
+            .context("Failed to spawn worker thread")?;
+
+        // Keep the current runtime alive until the worker exits
+        let control_inner = Arc::clone(&control);
+        lua.spawn_local(async move {
+            control_inner.exited.notified().await;
+        });
+
+        Ok(Self {
+            port: WorkerPort::from_parts(parent_parts),
+            control,
+        })
+    }
+
+    fn terminate(&self) {
+        self.control.terminated.store(true, Ordering::SeqCst);
+        self.control.terminate.notify_one();
+    }
+}
+
+impl LuaUserData for Worker {
+    fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field(LuaMetaMethod::Type, "Worker");
+        fields.add_field_method_get("running", |_, this| {
+            Ok(this.control.running.load(Ordering::SeqCst))
+        });
+    }
+
+    fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("post", |lua, this, value: LuaValue| {
+            this.port.post(lua, value)
+        });
+        methods.add_method("onMessage", |lua, this, callback: LuaFunction| {
+            this.port.on_message(lua, callback)
+        });
+        methods.add_method("terminate", |_, this, ()| {
+            this.terminate();
+            Ok(())
+        });
+    }
+}
+
+/**
+    Resolves and reads the module for a worker, relative to the calling
+    script, in the same way that `require` resolves relative paths.
+*/
+fn read_worker_module(lua: &Lua, module_path: &str) -> LuaResult<(String, Vec<u8>)> {
+    let source = lua
+        .inspect_stack(1)
+        .and_then(|info| info.source().source.map(|s| s.to_string()))
+        .ok_or_else(|| LuaError::runtime("Failed to get stack info for worker source"))?;
+    let abs_path = resolve_chunk_path(lua, &source)
+        .parent()
+        .ok_or_else(|| LuaError::runtime("Failed to get parent path of source"))?
+        .join(module_path);
+    let abs_path = path_clean::clean(abs_path);
+
+    let candidates = [
+        abs_path.clone(),
+        abs_path.with_extension("luau"),
+        abs_path.with_extension("lua"),
+        abs_path.join("init.luau"),
+        abs_path.join("init.lua"),
+    ];
+    for candidate in candidates {
+        if candidate.is_file() {
+            let contents = std::fs::read(&candidate).into_lua_err()?;
+            // NOTE: The worker starts out in the same working directory
+            // as this runtime, so the chunk name is made relative to it
+            let chunk_name =
+                pathdiff::diff_paths(&candidate, runtime_cwd(lua)).unwrap_or(candidate);
+            return Ok((chunk_name.to_string_lossy().to_string(), contents));
+        }
+    }
+
+    let rel_path = PathBuf::from(source)
+        .parent()
+        .map(|parent| path_clean::clean(parent.join(module_path)))
+        .unwrap_or_default();
+    Err(LuaError::runtime(format!(
+        "No file exists at the path '{}'",
+        rel_path.display()
+    )))
+}
+
+/**
+    Runs a worker module to completion, or until it is terminated, on the current thread.
+
+    NOTE: Terminating a worker stops its scheduler, but a worker that is busy running lua
+    code would never give control back to the scheduler, so lua is also interrupted
+*/
+fn run_worker(
+    control: Arc<WorkerControl>,
+    parts: WorkerPortParts,
+    args: Vec<String>,
+    cwd: PathBuf,
+    chunk_name: &str,
+    contents: Vec<u8>,
+) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to create worker runtime");
+
+    let mut runtime = Runtime::new().with_args(args);
+    // NOTE: The chunk name is relative to the working directory of the creating
+    // runtime, unless it has been removed since then, which is very unlikely
+    set_runtime_cwd(runtime.lua(), cwd).ok();
+    runtime.lua().set_app_data(WorkerParent {
+        parts: RefCell::new(Some(parts)),
+        port: RefCell::new(None),
+    });
+    let control_inner = Arc::clone(&control);
+    runtime.lua().set_interrupt(move |_| {
+        if control_inner.terminated.load(Ordering::SeqCst) {
+            Ok(VmState::Yield)
+        } else {
+            Ok(VmState::Continue)
+        }
+    });
+
+    rt.block_on(async {
+        tokio::select! {
+            _ = runtime.run(chunk_name, contents) => {},
+            () = control.terminate.notified() => {},
+        }
+    });
+
+    drop(runtime);
+    control.running.store(false, Ordering::SeqCst);
+    control.exited.notify_one();
+}
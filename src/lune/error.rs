@@ -3,6 +3,7 @@ use std::{
     fmt::{Debug, Display, Formatter, Result as FmtResult},
 };
 
+use console::colors_enabled_stderr;
 use mlua::prelude::*;
 
 use crate::lune::util::formatting::pretty_format_luau_error;
@@ -20,7 +21,7 @@ impl RuntimeError {
     /**
         Enables colorization of the error message when formatted using the [`Display`] trait.
 
-        Colorization is enabled by default.
+        Colorization is enabled by default, unless colors are disabled for stderr using [`crate::ColorChoice`].
     */
     #[doc(hidden)]
     pub fn enable_colors(mut self) -> Self {
@@ -31,7 +32,7 @@ impl RuntimeError {
     /**
         Disables colorization of the error message when formatted using the [`Display`] trait.
 
-        Colorization is enabled by default.
+        Colorization is enabled by default, unless colors are disabled for stderr using [`crate::ColorChoice`].
     */
     #[doc(hidden)]
     pub fn disable_colors(mut self) -> Self {
@@ -78,7 +79,7 @@ impl Display for RuntimeError {
         write!(
             f,
             "{}",
-            pretty_format_luau_error(&self.error, !self.disable_colors)
+            pretty_format_luau_error(&self.error, !self.disable_colors && colors_enabled_stderr())
         )
     }
 }
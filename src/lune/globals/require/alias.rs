@@ -3,7 +3,7 @@ use mlua::prelude::*;
 
 use crate::lune::util::{
     luaurc::LuauRc,
-    paths::{make_absolute_and_clean, CWD},
+    paths::{resolve_chunk_path, runtime_cwd},
 };
 
 use super::context::*;
@@ -20,7 +20,7 @@ where
 {
     let alias = alias.to_ascii_lowercase();
 
-    let parent = make_absolute_and_clean(source)
+    let parent = resolve_chunk_path(lua, source)
         .parent()
         .expect("how did a root path end up here..")
         .to_path_buf();
@@ -67,7 +67,7 @@ where
     // We now have our aliased path, our path require function just needs it
     // in a slightly different format with both absolute + relative to cwd
     let abs_path = luaurc.find_alias(&alias).unwrap().join(path);
-    let rel_path = pathdiff::diff_paths(&abs_path, CWD.as_path()).ok_or_else(|| {
+    let rel_path = pathdiff::diff_paths(&abs_path, runtime_cwd(lua)).ok_or_else(|| {
         LuaError::runtime(format!("failed to find relative path for alias '{alias}'"))
     })?;
 
@@ -14,7 +14,10 @@ use tokio::{
     },
 };
 
-use crate::lune::{builtins::LuneBuiltin, util::paths::CWD};
+use crate::lune::{
+    builtins::LuneBuiltin,
+    util::paths::{record_chunk_path, resolve_chunk_path},
+};
 
 /**
     Context containing cached results for all `require` operations.
@@ -55,22 +58,28 @@ impl RequireContext {
     */
     pub fn resolve_paths(
         &self,
+        lua: &Lua,
         source: impl AsRef<str>,
         path: impl AsRef<str>,
     ) -> LuaResult<(PathBuf, PathBuf)> {
-        let path = PathBuf::from(source.as_ref())
+        let source = source.as_ref();
+
+        /*
+            NOTE: Since scripts and required files have their absolute paths recorded,
+            `process.setCwd` never changes which file a require inside of them resolves
+            to, it is always relative to the requiring file, only other chunks such as
+            the ones given to `luau.load` will resolve using the new working directory
+        */
+        let abs_path = resolve_chunk_path(lua, source)
+            .parent()
+            .ok_or_else(|| LuaError::runtime("Failed to get parent path of source"))?
+            .join(path.as_ref());
+        let rel_path = Path::new(source)
             .parent()
             .ok_or_else(|| LuaError::runtime("Failed to get parent path of source"))?
             .join(path.as_ref());
 
-        let rel_path = path_clean::clean(path);
-        let abs_path = if rel_path.is_absolute() {
-            rel_path.to_path_buf()
-        } else {
-            CWD.join(&rel_path)
-        };
-
-        Ok((abs_path, rel_path))
+        Ok((path_clean::clean(abs_path), path_clean::clean(rel_path)))
     }
 
     /**
@@ -164,6 +173,7 @@ impl RequireContext {
         // Read the file at the given path, try to parse and
         // load it into a new lua thread that we can schedule
         let file_contents = fs::read(&abs_path).await?;
+        record_chunk_path(lua, rel_path.to_string_lossy(), abs_path);
         let file_thread = lua
             .load(file_contents)
             .set_name(rel_path.to_string_lossy().to_string());
@@ -9,6 +9,9 @@ use std::{
 
 use mlua::Lua;
 use mlua_luau_scheduler::Scheduler;
+use once_cell::sync::Lazy;
+
+use self::util::threads::{ThreadTracker, DEBUG_SCHEDULER};
 
 mod builtins;
 mod error;
@@ -17,6 +20,7 @@ mod globals;
 pub(crate) mod util;
 
 pub use error::RuntimeError;
+pub use util::colors::ColorChoice;
 
 #[derive(Debug)]
 pub struct Runtime {
@@ -30,12 +34,16 @@ impl Runtime {
     */
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        Lazy::force(&util::process::START_TIME);
+        ColorChoice::apply_default();
+
         let lua = Rc::new(Lua::new());
 
         lua.set_app_data(Rc::downgrade(&lua));
         lua.set_app_data(Vec::<String>::new());
 
         globals::inject_all(&lua).expect("Failed to inject globals");
+        ThreadTracker::install(&lua).expect("Failed to install thread tracker");
 
         Self {
             lua,
@@ -55,6 +63,13 @@ impl Runtime {
         self
     }
 
+    /**
+        Gets the Luau VM for this runtime.
+    */
+    pub(crate) fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
     /**
         Runs a Lune script inside of the current runtime.
 
@@ -77,14 +92,28 @@ impl Runtime {
         });
 
         // Load our "main" thread
-        let main = self
-            .lua
-            .load(script_contents.as_ref())
-            .set_name(script_name.as_ref());
+        util::paths::record_chunk_path(&self.lua, script_name.as_ref(), script_name.as_ref());
+        let main = self.lua.create_thread(
+            self.lua
+                .load(script_contents.as_ref())
+                .set_name(script_name.as_ref())
+                .into_function()?,
+        )?;
+        ThreadTracker::record(&self.lua, &main, 0)?;
 
         // Run it on our scheduler until it and any other spawned threads complete
         sched.push_thread_back(main, ())?;
-        sched.run().await;
+        if *DEBUG_SCHEDULER {
+            tokio::select! {
+                () = sched.run() => {},
+                () = ThreadTracker::watch_for_deadlocks(&self.lua) => {},
+            }
+            if sched.get_exit_code().is_none() {
+                ThreadTracker::report_unfinished(&self.lua);
+            }
+        } else {
+            sched.run().await;
+        }
 
         // Return the exit code - default to FAILURE if we got any errors
         Ok(sched.get_exit_code().unwrap_or({
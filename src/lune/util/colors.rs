@@ -0,0 +1,147 @@
+use std::{
+    env,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use console::{set_colors_enabled, set_colors_enabled_stderr, Term};
+
+static CONFIGURED: AtomicBool = AtomicBool::new(false);
+
+/**
+    When colors should be used for output written to stdout and stderr.
+
+    Colors are process-wide, and configured either explicitly using
+    [`ColorChoice::apply`], or automatically when creating a [`Runtime`].
+
+    [`Runtime`]: crate::Runtime
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /**
+        Use colors if the output stream is a terminal that supports them.
+
+        The `NO_COLOR` and `FORCE_COLOR` environment variables
+        may also be used to disable or enable colors entirely.
+    */
+    #[default]
+    Auto,
+    /// Always use colors, even if the output stream is not a terminal.
+    Always,
+    /// Never use colors.
+    Never,
+}
+
+impl ColorChoice {
+    /**
+        Resolves this color choice into whether or not colors should
+        be enabled for the given output stream, respecting environment
+        variables as described in <https://no-color.org> and <https://force-color.org>.
+    */
+    fn colors_enabled_for(self, term: &Term) -> bool {
+        self.colors_enabled_with(term, |name| env::var(name).ok())
+    }
+
+    fn colors_enabled_with(self, term: &Term, var: impl Fn(&str) -> Option<String>) -> bool {
+        let env_var = |name: &str| var(name).filter(|v| !v.is_empty());
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => {
+                if env_var("NO_COLOR").is_some() {
+                    false
+                } else if let Some(force) = env_var("FORCE_COLOR") {
+                    force != "0"
+                } else {
+                    term.features().colors_supported()
+                        && env_var("CLICOLOR").as_deref() != Some("0")
+                }
+            }
+        }
+    }
+
+    /**
+        Applies this color choice for all output in the current process.
+    */
+    pub fn apply(self) {
+        CONFIGURED.store(true, Ordering::SeqCst);
+        set_colors_enabled(self.colors_enabled_for(&Term::stdout()));
+        set_colors_enabled_stderr(self.colors_enabled_for(&Term::stderr()));
+    }
+
+    /**
+        Applies the default color choice, unless a choice has already been applied.
+    */
+    pub(crate) fn apply_default() {
+        if !CONFIGURED.load(Ordering::SeqCst) {
+            Self::default().apply();
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            s => Err(format!(
+                "invalid color choice '{s}' - expected one of 'auto', 'always', 'never'"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(choice: ColorChoice, vars: &[(&str, &str)]) -> bool {
+        choice.colors_enabled_with(&Term::stdout(), |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| (*value).to_string())
+        })
+    }
+
+    #[test]
+    fn explicit_choices() {
+        assert!(enabled(ColorChoice::Always, &[]));
+        assert!(enabled(ColorChoice::Always, &[("NO_COLOR", "1")]));
+        assert!(!enabled(ColorChoice::Never, &[]));
+        assert!(!enabled(ColorChoice::Never, &[("FORCE_COLOR", "1")]));
+    }
+
+    #[test]
+    fn no_color() {
+        assert!(!enabled(ColorChoice::Auto, &[("NO_COLOR", "1")]));
+        assert!(!enabled(
+            ColorChoice::Auto,
+            &[("NO_COLOR", "1"), ("FORCE_COLOR", "1")]
+        ));
+        assert!(enabled(
+            ColorChoice::Auto,
+            &[("NO_COLOR", ""), ("FORCE_COLOR", "1")]
+        ));
+    }
+
+    #[test]
+    fn force_color() {
+        assert!(enabled(ColorChoice::Auto, &[("FORCE_COLOR", "1")]));
+        assert!(enabled(
+            ColorChoice::Auto,
+            &[("FORCE_COLOR", "1"), ("CLICOLOR", "0")]
+        ));
+        assert!(!enabled(ColorChoice::Auto, &[("FORCE_COLOR", "0")]));
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("auto".parse(), Ok(ColorChoice::Auto));
+        assert_eq!(" Always ".parse(), Ok(ColorChoice::Always));
+        assert_eq!("NEVER".parse(), Ok(ColorChoice::Never));
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+}
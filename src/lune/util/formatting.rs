@@ -1,4 +1,4 @@
-use std::fmt::Write;
+use std::{cmp::Ordering, fmt::Write};
 
 use console::{colors_enabled, set_colors_enabled, style, Style};
 use mlua::prelude::*;
@@ -48,8 +48,8 @@ pub fn format_label<S: AsRef<str>>(s: S) -> String {
     )
 }
 
-pub fn format_style(style: Option<&'static Style>) -> String {
-    if cfg!(test) {
+pub fn format_style(style: Option<&'static Style>, colors_enabled: bool) -> String {
+    if !colors_enabled {
         "".to_string()
     } else if let Some(style) = style {
         // HACK: We have no direct way of referencing the ansi color code
@@ -100,11 +100,59 @@ pub fn style_from_style_str<S: AsRef<str>>(s: S) -> LuaResult<Option<&'static St
     })
 }
 
+/**
+    Configuration for how values should be pretty-formatted.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct ValueFormatConfig {
+    pub max_depth: usize,
+    pub sort_keys: bool,
+}
+
+impl Default for ValueFormatConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: MAX_FORMAT_DEPTH,
+            sort_keys: false,
+        }
+    }
+}
+
+/**
+    Orders table keys for formatting - numbers first, in ascending order,
+    followed by strings in alphabetical order, and then any other keys
+    ordered by their type name, to keep the ordering deterministic.
+*/
+fn compare_table_keys(a: &LuaValue, b: &LuaValue) -> Ordering {
+    fn rank(value: &LuaValue) -> u8 {
+        match value {
+            LuaValue::Integer(_) | LuaValue::Number(_) => 0,
+            LuaValue::String(_) => 1,
+            _ => 2,
+        }
+    }
+    fn number(value: &LuaValue) -> f64 {
+        match value {
+            LuaValue::Integer(i) => *i as f64,
+            LuaValue::Number(n) => *n,
+            _ => 0.0,
+        }
+    }
+    match (a, b) {
+        (LuaValue::String(a), LuaValue::String(b)) => a.as_bytes().cmp(b.as_bytes()),
+        _ if rank(a) == 0 && rank(b) == 0 => number(a).total_cmp(&number(b)),
+        _ => rank(a)
+            .cmp(&rank(b))
+            .then_with(|| a.type_name().cmp(b.type_name())),
+    }
+}
+
 pub fn pretty_format_value(
     buffer: &mut String,
     value: &LuaValue,
     parent_table_addr: Option<String>,
     depth: usize,
+    config: &ValueFormatConfig,
 ) -> std::fmt::Result {
     match &value {
         LuaValue::Nil => write!(buffer, "nil")?,
@@ -125,7 +173,7 @@ pub fn pretty_format_value(
         LuaValue::Table(ref tab) => {
             let table_addr = Some(format!("{:p}", tab.to_pointer()));
 
-            if depth >= MAX_FORMAT_DEPTH {
+            if depth >= config.max_depth {
                 write!(buffer, "{}", STYLE_DIM.apply_to("{ ... }"))?;
             } else if let Some(s) = call_table_tostring_metamethod(tab) {
                 write!(buffer, "{s}")?;
@@ -135,8 +183,15 @@ pub fn pretty_format_value(
                 let mut is_empty = false;
                 let depth_indent = INDENT.repeat(depth);
                 write!(buffer, "{}", STYLE_DIM.apply_to("{"))?;
-                for pair in tab.clone().pairs::<LuaValue, LuaValue>() {
-                    let (key, value) = pair.unwrap();
+                let mut pairs = tab
+                    .clone()
+                    .pairs::<LuaValue, LuaValue>()
+                    .map(Result::unwrap)
+                    .collect::<Vec<_>>();
+                if config.sort_keys {
+                    pairs.sort_by(|(a, _), (b, _)| compare_table_keys(a, b));
+                }
+                for (key, value) in pairs {
                     match &key {
                         LuaValue::String(s) if can_be_plain_lua_table_key(s) => write!(
                             buffer,
@@ -153,11 +208,18 @@ pub fn pretty_format_value(
                                 &key,
                                 parent_table_addr.clone(),
                                 depth + 1,
+                                config,
                             )?;
                             write!(buffer, "] {} ", STYLE_DIM.apply_to("="))?;
                         }
                     }
-                    pretty_format_value(buffer, &value, parent_table_addr.clone(), depth + 1)?;
+                    pretty_format_value(
+                        buffer,
+                        &value,
+                        parent_table_addr.clone(),
+                        depth + 1,
+                        config,
+                    )?;
                     write!(buffer, "{}", STYLE_DIM.apply_to(","))?;
                     is_empty = false;
                 }
@@ -194,6 +256,13 @@ pub fn pretty_format_value(
 }
 
 pub fn pretty_format_multi_value(multi: &LuaMultiValue) -> LuaResult<String> {
+    pretty_format_multi_value_with_config(multi, &ValueFormatConfig::default())
+}
+
+pub fn pretty_format_multi_value_with_config(
+    multi: &LuaMultiValue,
+    config: &ValueFormatConfig,
+) -> LuaResult<String> {
     let mut buffer = String::new();
     let mut counter = 0;
     for value in multi {
@@ -203,7 +272,7 @@ pub fn pretty_format_multi_value(multi: &LuaMultiValue) -> LuaResult<String> {
         } else {
             let addr = format!("{:p}", value.to_pointer());
 
-            pretty_format_value(&mut buffer, value, Some(addr), 0).into_lua_err()?;
+            pretty_format_value(&mut buffer, value, Some(addr), 0, config).into_lua_err()?;
         }
         if counter < multi.len() {
             write!(&mut buffer, " ").into_lua_err()?;
@@ -214,8 +283,9 @@ pub fn pretty_format_multi_value(multi: &LuaMultiValue) -> LuaResult<String> {
 
 pub fn pretty_format_luau_error(e: &LuaError, colorized: bool) -> String {
     let previous_colors_enabled = if !colorized {
+        let previous = colors_enabled();
         set_colors_enabled(false);
-        Some(colors_enabled())
+        Some(previous)
     } else {
         None
     };
@@ -1,8 +1,11 @@
 mod table_builder;
 
+pub mod colors;
 pub mod formatting;
 pub mod luaurc;
 pub mod paths;
+pub mod process;
+pub mod threads;
 pub mod traits;
 
 pub use table_builder::TableBuilder;
@@ -1,8 +1,11 @@
 use std::{
+    collections::HashMap,
     env::current_dir,
+    io,
     path::{Path, PathBuf},
 };
 
+use mlua::Lua;
 use once_cell::sync::Lazy;
 use path_clean::PathClean;
 
@@ -19,3 +22,97 @@ pub fn make_absolute_and_clean(path: impl AsRef<Path>) -> PathBuf {
         path.clean()
     }
 }
+
+/**
+    The working directory for a single runtime, which may be changed
+    using `process.setCwd` without affecting the cwd of the process itself.
+
+    Defaults to the working directory that Lune was started in.
+*/
+#[derive(Debug, Clone)]
+struct RuntimeCwd(PathBuf);
+
+/**
+    Gets the current working directory for the given runtime.
+*/
+pub fn runtime_cwd(lua: &Lua) -> PathBuf {
+    match lua.app_data_ref::<RuntimeCwd>() {
+        Some(cwd) => cwd.0.clone(),
+        None => CWD.clone(),
+    }
+}
+
+/**
+    Sets the current working directory for the given runtime.
+
+    The path will be resolved relative to the current working directory
+    of the runtime, and must point to an existing directory. Similar to
+    `cd` in shells, any symlinks in the path are kept as they are.
+*/
+pub fn set_runtime_cwd(lua: &Lua, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = resolve_runtime_path(lua, path).clean();
+    if !path.try_exists()? {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("'{}' does not exist", path.display()),
+        ));
+    }
+    if !path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a directory", path.display()),
+        ));
+    }
+    lua.set_app_data(RuntimeCwd(path));
+    Ok(())
+}
+
+/**
+    Resolves a path relative to the current working directory of the
+    given runtime, leaving absolute paths untouched.
+*/
+pub fn resolve_runtime_path(lua: &Lua, path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    if path.is_relative() {
+        runtime_cwd(lua).join(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/**
+    Absolute paths of chunks that were loaded from files, by chunk name.
+*/
+#[derive(Debug, Default)]
+struct ChunkPaths(HashMap<String, PathBuf>);
+
+/**
+    Records the path of a chunk that was loaded from a file, resolving it
+    relative to the current working directory of the given runtime.
+
+    Chunk names are relative to the working directory at the time that the chunk was loaded,
+    so this lets paths that are relative to a chunk, such as the ones given to `require`,
+    keep resolving to the same files after the working directory has been changed.
+*/
+pub fn record_chunk_path(lua: &Lua, chunk_name: impl Into<String>, path: impl AsRef<Path>) {
+    let path = resolve_runtime_path(lua, path).clean();
+    if let Some(mut paths) = lua.app_data_mut::<ChunkPaths>() {
+        paths.0.insert(chunk_name.into(), path);
+        return;
+    }
+    let mut paths = ChunkPaths::default();
+    paths.0.insert(chunk_name.into(), path);
+    lua.set_app_data(paths);
+}
+
+/**
+    Resolves a chunk name into an absolute path, using the recorded path of the chunk if
+    there is one, and the current working directory of the given runtime otherwise.
+*/
+pub fn resolve_chunk_path(lua: &Lua, chunk_name: impl AsRef<str>) -> PathBuf {
+    let chunk_name = chunk_name.as_ref();
+    let recorded = lua
+        .app_data_ref::<ChunkPaths>()
+        .and_then(|paths| paths.0.get(chunk_name).cloned());
+    recorded.unwrap_or_else(|| resolve_runtime_path(lua, chunk_name).clean())
+}
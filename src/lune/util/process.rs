@@ -0,0 +1,8 @@
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+
+/**
+    The time at which Lune started, forced when the first runtime is created.
+*/
+pub static START_TIME: Lazy<Instant> = Lazy::new(Instant::now);
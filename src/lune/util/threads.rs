@@ -0,0 +1,468 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    env,
+    fmt::Write as _,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use mlua::prelude::*;
+use mlua_luau_scheduler::LuaSpawnExt;
+use once_cell::sync::Lazy;
+use tokio::sync::Notify;
+
+use super::formatting::format_label;
+
+const THREADS_KEY: &str = "__lune_tracked_threads";
+const ASYNC_POLL_SOURCE: &str = "__mlua_async_poll";
+const MAX_TRACEBACK_FRAMES: usize = 16;
+const DEADLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+    If scheduler debugging was enabled using the `LUNE_DEBUG_SCHEDULER` environment variable.
+
+    When enabled, all spawned threads are tracked together with stack traces for where they
+    were created, possible deadlocks are reported while running, and any threads that never finished are
+    listed once the scheduler has nothing left to run.
+*/
+pub static DEBUG_SCHEDULER: Lazy<bool> = Lazy::new(|| {
+    env::var("LUNE_DEBUG_SCHEDULER").is_ok_and(|value| !value.is_empty() && value != "0")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadStatus {
+    /// The thread is currently running, or resuming another thread.
+    Running,
+    /// The thread has been scheduled, but has not started running yet.
+    Deferred,
+    /// The thread is waiting for a timer, such as `task.wait`.
+    Timer,
+    /// The thread is waiting for a native async operation, such as reading a file.
+    Io,
+    /// The thread is waiting for another thread, such as when receiving from a channel.
+    Blocked,
+    /// The thread has yielded, and can only be resumed manually.
+    Suspended,
+}
+
+impl ThreadStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Deferred => "deferred",
+            Self::Timer => "timer",
+            Self::Io => "io",
+            Self::Blocked => "blocked",
+            Self::Suspended => "suspended",
+        }
+    }
+
+    /**
+        Checks if a thread with this status will be resumed by the scheduler on its own.
+    */
+    fn is_pending(self) -> bool {
+        matches!(
+            self,
+            Self::Running | Self::Deferred | Self::Timer | Self::Io
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ThreadWait {
+    status: ThreadStatus,
+    reason: String,
+}
+
+/**
+    Information about a single live thread, as given by `task.info`.
+*/
+#[derive(Debug, Clone)]
+pub struct ThreadInfo<'lua> {
+    thread: LuaThread<'lua>,
+    id: u64,
+    status: ThreadStatus,
+    waiting_on: Option<String>,
+    location: Option<String>,
+    created_at: Option<String>,
+}
+
+impl<'lua> IntoLua<'lua> for ThreadInfo<'lua> {
+    fn into_lua(self, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        let table = lua.create_table_with_capacity(0, 6)?;
+        table.set("thread", self.thread)?;
+        table.set("id", self.id)?;
+        table.set("status", self.status.as_str())?;
+        table.set("waitingOn", self.waiting_on)?;
+        table.set("location", self.location)?;
+        table.set("createdAt", self.created_at)?;
+        Ok(LuaValue::Table(table))
+    }
+}
+
+/**
+    Keeps track of threads on the scheduler, and what they are waiting for.
+
+    Threads are stored in a table with weak keys in the lua registry, so tracking
+    a thread never keeps it alive, and what a thread is waiting for is only stored
+    while it is waiting, using [`ThreadTracker::wait`].
+*/
+#[derive(Debug, Default)]
+pub struct ThreadTracker {
+    waits: RefCell<HashMap<usize, ThreadWait>>,
+    sources: Cell<usize>,
+    next_id: Cell<u64>,
+    closed: Rc<Notify>,
+}
+
+impl ThreadTracker {
+    /**
+        Creates a new thread tracker and stores it in the app data of the given Lua VM.
+    */
+    pub fn install(lua: &Lua) -> LuaResult<()> {
+        let threads = lua.create_table()?;
+        let meta = lua.create_table()?;
+        meta.set("__mode", "k")?;
+        threads.set_metatable(Some(meta));
+        lua.set_named_registry_value(THREADS_KEY, threads)?;
+        lua.set_app_data(Rc::new(Self::default()));
+        Ok(())
+    }
+
+    fn get(lua: &Lua) -> Rc<Self> {
+        Rc::clone(
+            &lua.app_data_ref::<Rc<Self>>()
+                .expect("Missing thread tracker in app data"),
+        )
+    }
+
+    fn entry<'lua>(&self, lua: &'lua Lua, thread: &LuaThread<'lua>) -> LuaResult<LuaTable<'lua>> {
+        let threads = lua.named_registry_value::<LuaTable>(THREADS_KEY)?;
+        if let Some(entry) = threads.raw_get::<_, Option<LuaTable>>(thread.clone())? {
+            return Ok(entry);
+        }
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        let entry = lua.create_table()?;
+        entry.raw_set("id", id)?;
+        threads.raw_set(thread.clone(), entry.clone())?;
+        Ok(entry)
+    }
+
+    /**
+        Starts tracking the given thread, storing where it was created from
+        if scheduler debugging is enabled.
+
+        The stack level should be the level of the lua function that created the thread.
+    */
+    pub fn record(lua: &Lua, thread: &LuaThread, level: usize) -> LuaResult<()> {
+        let entry = Self::get(lua).entry(lua, thread)?;
+        if !*DEBUG_SCHEDULER || !entry.raw_get::<_, LuaValue>("createdAt")?.is_nil() {
+            return Ok(());
+        }
+        let frames = (level..)
+            .map_while(|level| lua.inspect_stack(level))
+            .filter_map(|debug| {
+                let source = debug.source();
+                if source.what == "C" {
+                    return None;
+                }
+                let name = source.source.or(source.short_src)?;
+                if name.contains(ASYNC_POLL_SOURCE) {
+                    return None;
+                }
+                Some(format!("{}:{}", clean_source(&name), debug.curr_line()))
+            })
+            .take(MAX_TRACEBACK_FRAMES)
+            .collect::<Vec<_>>();
+        if !frames.is_empty() {
+            entry.raw_set("createdAt", frames.join("\n"))?;
+        }
+        Ok(())
+    }
+
+    /**
+        Marks the currently running thread as waiting while the given future is pending.
+    */
+    pub fn wait<F: Future>(
+        lua: &Lua,
+        status: ThreadStatus,
+        reason: impl Into<String>,
+        future: F,
+    ) -> LuaResult<TrackedWait<F>> {
+        let tracker = Self::get(lua);
+        let thread = lua.current_thread();
+        tracker.entry(lua, &thread)?;
+        let key = thread.to_pointer() as usize;
+        let previous = tracker.waits.borrow_mut().insert(
+            key,
+            ThreadWait {
+                status,
+                reason: reason.into(),
+            },
+        );
+        Ok(TrackedWait {
+            future: Box::pin(future),
+            waker: None,
+            _guard: WaitGuard {
+                tracker,
+                key,
+                previous,
+            },
+        })
+    }
+
+    /**
+        Spawns a background task that may wake up threads, such as a server or a timer.
+
+        While scheduler debugging is enabled, this prevents threads waiting
+        for the task from being reported as deadlocked until it has finished.
+    */
+    pub fn spawn_source(lua: &Lua, fut: impl Future<Output = ()> + 'static) {
+        if !*DEBUG_SCHEDULER {
+            lua.spawn_local(fut);
+            return;
+        }
+        let tracker = Self::get(lua);
+        tracker.sources.set(tracker.sources.get() + 1);
+        let guard = SourceGuard { tracker };
+        lua.spawn_local(async move {
+            let _guard = guard;
+            fut.await;
+        });
+    }
+
+    /**
+        Notifies anyone waiting using [`ThreadTracker::closed`] that a thread was closed.
+    */
+    pub fn notify_closed(lua: &Lua) {
+        Self::get(lua).closed.notify_waiters();
+    }
+
+    /**
+        Gets a notification that is triggered whenever a thread is closed
+        using `task.cancel` or `coroutine.close`, after it has been closed.
+    */
+    pub fn closed(lua: &Lua) -> Rc<Notify> {
+        Rc::clone(&Self::get(lua).closed)
+    }
+
+    /**
+        Collects information about all live threads that are currently being tracked.
+    */
+    pub fn collect<'lua>(lua: &'lua Lua) -> LuaResult<Vec<ThreadInfo<'lua>>> {
+        let tracker = Self::get(lua);
+        let threads = lua.named_registry_value::<LuaTable>(THREADS_KEY)?;
+        let co_status = lua
+            .globals()
+            .get::<_, LuaTable>("coroutine")?
+            .get::<_, LuaFunction>("status")?;
+        let debug_info = lua
+            .globals()
+            .get::<_, LuaTable>("debug")?
+            .get::<_, LuaFunction>("info")?;
+
+        let mut infos = Vec::new();
+        for pair in threads.pairs::<LuaThread, LuaTable>() {
+            let (thread, entry) = pair?;
+            let co = co_status.call::<_, String>(thread.clone())?;
+            if co == "dead" {
+                continue;
+            }
+
+            let mut frames = Vec::new();
+            for level in 0.. {
+                let (source, line) = debug_info.call::<_, (Option<String>, Option<i64>)>((
+                    thread.clone(),
+                    level,
+                    "sl",
+                ))?;
+                match source {
+                    None => break,
+                    Some(source) => frames.push((source, line.unwrap_or(-1))),
+                }
+            }
+
+            let wait = tracker
+                .waits
+                .borrow()
+                .get(&(thread.to_pointer() as usize))
+                .cloned();
+            let status = if co == "running" || co == "normal" {
+                ThreadStatus::Running
+            } else if let Some(wait) = &wait {
+                wait.status
+            } else if frames.is_empty() {
+                ThreadStatus::Deferred
+            } else if frames
+                .iter()
+                .any(|(source, _)| source.contains(ASYNC_POLL_SOURCE))
+            {
+                ThreadStatus::Io
+            } else {
+                ThreadStatus::Suspended
+            };
+            let location = frames
+                .iter()
+                .find(|(source, line)| *line >= 0 && !source.contains(ASYNC_POLL_SOURCE))
+                .map(|(source, line)| format!("{}:{line}", clean_source(source)));
+
+            infos.push(ThreadInfo {
+                thread,
+                id: entry.raw_get("id")?,
+                status,
+                waiting_on: wait.map(|wait| wait.reason),
+                location,
+                created_at: entry.raw_get("createdAt")?,
+            });
+        }
+
+        infos.sort_by_key(|info| info.id);
+        Ok(infos)
+    }
+
+    /**
+        Checks for threads that are blocked with nothing left to wake them up, forever.
+
+        This should be polled together with the scheduler, and will print a warning
+        whenever the scheduler is idle and only has blocked threads left to resume.
+    */
+    pub async fn watch_for_deadlocks(lua: &Lua) {
+        let mut warned = false;
+        loop {
+            tokio::time::sleep(DEADLOCK_CHECK_INTERVAL).await;
+            let Ok(threads) = Self::collect(lua) else {
+                continue;
+            };
+            let deadlocked = Self::get(lua).sources.get() == 0
+                && threads.iter().any(|t| t.status == ThreadStatus::Blocked)
+                && !threads.iter().any(|t| t.status.is_pending());
+            if deadlocked && !warned {
+                eprintln!(
+                    "{}\nPossible deadlock - all threads are blocked, with nothing left to wake them up:\n{}",
+                    format_label("warn"),
+                    format_thread_list(&threads)
+                );
+            }
+            warned = deadlocked;
+        }
+    }
+
+    /**
+        Prints any tracked threads that never finished running, once the scheduler has stopped.
+    */
+    pub fn report_unfinished(lua: &Lua) {
+        let Ok(threads) = Self::collect(lua) else {
+            return;
+        };
+        if !threads.is_empty() {
+            eprintln!(
+                "{}\nScheduler stopped with {} thread(s) that never finished:\n{}",
+                format_label("warn"),
+                threads.len(),
+                format_thread_list(&threads)
+            );
+        }
+    }
+}
+
+/**
+    Future returned by [`ThreadTracker::wait`], unmarks the thread when dropped.
+
+    NOTE: A thread that gets cancelled while waiting is eventually garbage collected,
+    which drops this future without it ever completing, and the scheduler would then
+    never hear from the thread again - so we wake it when dropped to let it clean up
+*/
+pub struct TrackedWait<F: Future> {
+    future: Pin<Box<F>>,
+    waker: Option<Waker>,
+    _guard: WaitGuard,
+}
+
+impl<F: Future> Future for TrackedWait<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let poll = self.future.as_mut().poll(cx);
+        self.waker = match poll {
+            Poll::Pending => Some(cx.waker().clone()),
+            Poll::Ready(_) => None,
+        };
+        poll
+    }
+}
+
+impl<F: Future> Drop for TrackedWait<F> {
+    fn drop(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WaitGuard {
+    tracker: Rc<ThreadTracker>,
+    key: usize,
+    previous: Option<ThreadWait>,
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        let mut waits = self.tracker.waits.borrow_mut();
+        match self.previous.take() {
+            Some(previous) => waits.insert(self.key, previous),
+            None => waits.remove(&self.key),
+        };
+    }
+}
+
+/**
+    Guard held by tasks spawned using [`ThreadTracker::spawn_source`], unmarks the source when dropped.
+*/
+#[derive(Debug)]
+struct SourceGuard {
+    tracker: Rc<ThreadTracker>,
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        self.tracker.sources.set(self.tracker.sources.get() - 1);
+    }
+}
+
+fn clean_source(source: &str) -> &str {
+    source
+        .strip_prefix("[string \"")
+        .and_then(|s| s.strip_suffix("\"]"))
+        .unwrap_or(source)
+}
+
+fn format_thread_list(threads: &[ThreadInfo]) -> String {
+    let mut list = String::new();
+    for info in threads {
+        write!(list, "  thread #{} is {}", info.id, info.status.as_str()).unwrap();
+        if let Some(waiting_on) = &info.waiting_on {
+            write!(list, " on {waiting_on}").unwrap();
+        }
+        if let Some(location) = &info.location {
+            write!(list, " at {location}").unwrap();
+        }
+        list.push('\n');
+        if let Some(created_at) = &info.created_at {
+            for (index, frame) in created_at.lines().enumerate() {
+                let prefix = if index == 0 {
+                    "created at"
+                } else {
+                    "          "
+                };
+                writeln!(list, "    {prefix} {frame}").unwrap();
+            }
+        }
+    }
+    list.trim_end().to_string()
+}
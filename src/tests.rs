@@ -1,11 +1,9 @@
 use std::process::ExitCode;
 
 use anyhow::Result;
-use console::set_colors_enabled;
-use console::set_colors_enabled_stderr;
 use tokio::fs::read_to_string;
 
-use crate::Runtime;
+use crate::{ColorChoice, Runtime};
 
 const ARGS: &[&str] = &["Foo", "Bar"];
 
@@ -15,8 +13,7 @@ macro_rules! create_tests {
         async fn $name() -> Result<ExitCode> {
             // Disable styling for stdout and stderr since
             // some tests rely on output not being styled
-            set_colors_enabled(false);
-            set_colors_enabled_stderr(false);
+            ColorChoice::Never.apply();
             // The rest of the test logic can continue as normal
             let full_name = format!("tests/{}.luau", $value);
             let script = read_to_string(&full_name).await?;
@@ -76,12 +73,23 @@ create_tests! {
     process_cwd: "process/cwd",
     process_env: "process/env",
     process_exit: "process/exit",
+    process_info: "process/info",
+    process_create_kill: "process/create/kill",
+    process_create_pty: "process/create/pty",
+    process_create_stream: "process/create/stream",
+    process_pipeline_basic: "process/pipeline/basic",
+    process_setcwd: "process/setcwd",
+    process_signal_handler: "process/signal/handler",
     process_spawn_async: "process/spawn/async",
     process_spawn_basic: "process/spawn/basic",
     process_spawn_cwd: "process/spawn/cwd",
+    process_spawn_detached: "process/spawn/detached",
+    process_spawn_escape: "process/spawn/escape",
     process_spawn_shell: "process/spawn/shell",
     process_spawn_stdin: "process/spawn/stdin",
+    process_spawn_status: "process/spawn/status",
     process_spawn_stdio: "process/spawn/stdio",
+    process_spawn_timeout: "process/spawn/timeout",
 
     regex_general: "regex/general",
     regex_metamethods: "regex/metamethods",
@@ -93,6 +101,7 @@ create_tests! {
     require_async_sequential: "require/tests/async_sequential",
     require_builtins: "require/tests/builtins",
     require_children: "require/tests/children",
+    require_cwd: "require/tests/cwd",
     require_init: "require/tests/init",
     require_invalid: "require/tests/invalid",
     require_multi_ext: "require/tests/multi_ext",
@@ -110,24 +119,45 @@ create_tests! {
     global_typeof: "globals/typeof",
     global_warn: "globals/warn",
 
+    serde_cbor_roundtrip: "serde/cbor/roundtrip",
     serde_compression_files: "serde/compression/files",
     serde_compression_roundtrip: "serde/compression/roundtrip",
+    serde_csv_roundtrip: "serde/csv/roundtrip",
+    serde_ini_roundtrip: "serde/ini/roundtrip",
     serde_json_decode: "serde/json/decode",
     serde_json_encode: "serde/json/encode",
+    serde_json_lines: "serde/json/lines",
+    serde_json_markers: "serde/json/markers",
+    serde_json_ordered: "serde/json/ordered",
+    serde_json_stream: "serde/json/stream",
+    serde_msgpack_roundtrip: "serde/msgpack/roundtrip",
     serde_toml_decode: "serde/toml/decode",
+    serde_toml_edit: "serde/toml/edit",
     serde_toml_encode: "serde/toml/encode",
+    serde_xml_roundtrip: "serde/xml/roundtrip",
 
     stdio_format: "stdio/format",
     stdio_color: "stdio/color",
+    stdio_progress: "stdio/progress",
+    stdio_read: "stdio/read",
     stdio_style: "stdio/style",
+    stdio_table: "stdio/table",
+    stdio_terminal: "stdio/terminal",
     stdio_write: "stdio/write",
     stdio_ewrite: "stdio/ewrite",
 
     task_cancel: "task/cancel",
+    task_channel: "task/channel",
     task_defer: "task/defer",
     task_delay: "task/delay",
+    task_group: "task/group",
+    task_info: "task/info",
+    task_interval: "task/interval",
     task_spawn: "task/spawn",
+    task_sync: "task/sync",
+    task_timeout: "task/timeout",
     task_wait: "task/wait",
+    task_worker: "task/worker",
 }
 
 #[cfg(feature = "roblox")]